@@ -0,0 +1,646 @@
+use crate::effects::{Balance, Downmix, EffectControls, Equalizer, EqualizerGains};
+use crate::http_stream::HttpStream;
+use crate::loudness;
+use crate::metadata::AudioFormat;
+use crate::meter::{LevelMeter, Levels, MeterControl};
+use crate::recording::{Capture, CaptureControl, CaptureSender};
+use crate::sniff::{SniffedFormat, sniff, sniff_reader};
+use crate::sound_player::{SoundPlayerError, SoundPlayerResult};
+use crate::stretch::{StretchControl, TimeStretch};
+use log::error;
+use rodio::buffer::SamplesBuffer;
+use rodio::cpal::traits::HostTrait;
+use rodio::source::SeekError;
+use rodio::{
+    ChannelCount, Decoder, DeviceTrait, OutputStream, OutputStreamBuilder, Sample, SampleRate,
+    Sink, Source, cpal,
+};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Playback controls for a single loaded track. Mirrors the parts of
+/// `rodio::Sink` that `SoundPlayer` relies on.
+pub trait AudioSink: Send + Sync + 'static {
+    fn play(&self);
+    fn pause(&self);
+    fn stop(&self);
+    fn is_paused(&self) -> bool;
+    /// Whether the track has played to the end (or was stopped).
+    fn empty(&self) -> bool;
+    fn volume(&self) -> f32;
+    fn set_volume(&self, volume: f32);
+    fn set_speed(&self, speed: f32);
+    /// Changes the playback rate like `set_speed`, but keeps the pitch.
+    fn set_tempo(&self, tempo: f32);
+    /// Stereo balance from -1.0 (left) to 1.0 (right).
+    fn set_pan(&self, pan: f32);
+    /// Whether all channels play their average.
+    fn set_mono(&self, mono: bool);
+    /// Per-band gains in dB for `EQ_BANDS_HZ`.
+    fn set_equalizer(&self, gains: EqualizerGains);
+    fn try_seek(&self, position: Duration) -> Result<(), SeekError>;
+    fn get_pos(&self) -> Duration;
+    /// Copies the samples being played to `sender` until the track ends, or stops
+    /// copying with `None`. Volume is applied after the copy is taken.
+    fn record(&self, sender: Option<CaptureSender>);
+    /// Starts or stops measuring the levels of the track as it plays.
+    fn set_metering(&self, enabled: bool);
+    /// Levels since the last call while metering, at the sink's volume; `None`
+    /// if nothing was measured.
+    fn take_levels(&self) -> Option<Levels>;
+}
+
+/// Where a track's audio comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackSource {
+    File(PathBuf),
+    /// Streamed over HTTP as it plays.
+    Url(String),
+    /// Encoded audio sent along with the order; `format` is its file extension.
+    Inline {
+        data: Arc<[u8]>,
+        format: String,
+    },
+    /// A file decoded in full and played backwards; limited to
+    /// `MAX_REVERSE_DURATION`.
+    Reversed(PathBuf),
+}
+
+impl TrackSource {
+    /// Whether tracks from this source can seek in both directions. Streams are
+    /// decoded as they arrive and can only skip ahead.
+    pub fn seekable(&self) -> bool {
+        !matches!(self, TrackSource::Url(_))
+    }
+}
+
+impl fmt::Display for TrackSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackSource::File(path) => write!(f, "{}", path.display()),
+            TrackSource::Url(url) => f.write_str(url),
+            TrackSource::Inline { format, .. } => write!(f, "inline {} audio", format),
+            TrackSource::Reversed(path) => write!(f, "{} (reversed)", path.display()),
+        }
+    }
+}
+
+/// How a newly loaded track should start out.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadOptions {
+    pub volume: f32,
+    pub speed: f32,
+    pub tempo: f32,
+    pub pan: f32,
+    pub mono: bool,
+    pub equalizer: EqualizerGains,
+    pub fade_in: Option<Duration>,
+    /// Whether levels are measured from the start.
+    pub metering: bool,
+    /// Whether the sink starts out paused, so nothing is heard until `play`.
+    pub paused: bool,
+}
+
+pub struct LoadedTrack<S> {
+    pub sink: S,
+    /// Total length, or `None` when the format doesn't report it.
+    pub duration: Option<Duration>,
+    /// Format the track decodes to, when the backend knows it.
+    pub format: Option<AudioFormat>,
+}
+
+/// Something that can turn audio files into playing sinks. `SoundPlayer` holds the
+/// queue, repeat and fade logic on top of it.
+pub trait AudioBackend: Send + 'static {
+    type Sink: AudioSink;
+    /// A track that has been opened and is ready to start.
+    type Prepared: Send + 'static;
+
+    /// Opens `source` and does as much of the decoding work as possible without
+    /// an output. Needs no backend instance, so it can run on any thread.
+    fn prepare(source: &TrackSource) -> SoundPlayerResult<Self::Prepared>;
+
+    /// Puts a prepared track on a new sink that starts playing immediately,
+    /// unless `options.paused` is set.
+    fn start(
+        &self,
+        prepared: Self::Prepared,
+        options: LoadOptions,
+    ) -> SoundPlayerResult<LoadedTrack<Self::Sink>>;
+
+    /// Decodes `source` onto a new sink that starts playing immediately.
+    fn load(
+        &self,
+        source: &TrackSource,
+        options: LoadOptions,
+    ) -> SoundPlayerResult<LoadedTrack<Self::Sink>> {
+        self.start(Self::prepare(source)?, options)
+    }
+
+    /// Decodes `source` in full and returns its integrated loudness in LUFS, or
+    /// `None` if it has none to speak of. Backends that never decode audio
+    /// don't measure anything.
+    fn measure_loudness(_source: &TrackSource) -> SoundPlayerResult<Option<f64>> {
+        Ok(None)
+    }
+
+    /// Names of the output devices this backend can switch to.
+    fn list_devices(&self) -> SoundPlayerResult<Vec<String>>;
+
+    /// The device picked with `set_device`, or `None` while output goes to the
+    /// default device.
+    fn device_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the output stream is believed to still work, so a vanished device
+    /// can be noticed before playing anything fails on it.
+    fn stream_healthy(&self) -> bool {
+        true
+    }
+
+    /// Moves output to the device called `name`. Sinks loaded before the switch
+    /// are not carried over.
+    fn set_device(&mut self, name: &str) -> SoundPlayerResult<()>;
+
+    /// Opens the output stream again after it failed, on the same device if it's
+    /// still there and on the default one otherwise. Existing sinks are not
+    /// carried over.
+    fn reopen(&mut self) -> SoundPlayerResult<()>;
+
+    /// A backend for another, independent player that outputs wherever this one
+    /// currently does. Switching devices on either later affects only that one.
+    fn fork(&self) -> SoundPlayerResult<Self>
+    where
+        Self: Sized;
+}
+
+/// Plays through the default audio host via rodio.
+pub struct RodioBackend {
+    /// Shared with backends forked from this one until either switches streams.
+    stream_handle: Arc<OutputStream>,
+    /// Set from the audio thread once the device behind `stream_handle` is gone.
+    stream_lost: Arc<AtomicBool>,
+    /// Device picked with `set_device`; `None` for the default output.
+    device_name: Option<String>,
+}
+
+impl RodioBackend {
+    pub fn new() -> SoundPlayerResult<Self> {
+        let stream_lost = Arc::new(AtomicBool::new(false));
+        let stream_handle = Arc::new(open_stream(None, &stream_lost)?);
+        Ok(Self {
+            stream_handle,
+            stream_lost,
+            device_name: None,
+        })
+    }
+}
+
+fn find_device(name: &str) -> SoundPlayerResult<cpal::Device> {
+    cpal::default_host()
+        .output_devices()?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+        .ok_or_else(|| SoundPlayerError::DeviceNotFound {
+            name: name.to_string(),
+        })
+}
+
+/// Opens a stream on `device`, or the default output if `None`, that raises
+/// `stream_lost` when the device disappears.
+fn open_stream(
+    device: Option<cpal::Device>,
+    stream_lost: &Arc<AtomicBool>,
+) -> SoundPlayerResult<OutputStream> {
+    let lost = stream_lost.clone();
+    let on_error = move |e: cpal::StreamError| {
+        error!("Audio stream error: {}", e);
+        if matches!(e, cpal::StreamError::DeviceNotAvailable) {
+            lost.store(true, Ordering::SeqCst);
+        }
+    };
+    let stream = match device {
+        Some(device) => OutputStreamBuilder::from_device(device)?
+            .with_error_callback(on_error)
+            .open_stream()?,
+        None => OutputStreamBuilder::from_default_device()?
+            .with_error_callback(on_error)
+            .open_stream_or_fallback()?,
+    };
+    stream_lost.store(false, Ordering::SeqCst);
+    Ok(stream)
+}
+
+impl AudioBackend for RodioBackend {
+    type Sink = RodioSink;
+    type Prepared = Prefetch<Box<dyn Source + Send>>;
+
+    fn prepare(source: &TrackSource) -> SoundPlayerResult<Self::Prepared> {
+        Ok(Prefetch::new(open_decoder(source)?, PREFETCH))
+    }
+
+    fn start(
+        &self,
+        decoder: Self::Prepared,
+        options: LoadOptions,
+    ) -> SoundPlayerResult<LoadedTrack<RodioSink>> {
+        if self.stream_lost.load(Ordering::SeqCst) {
+            return Err(SoundPlayerError::InvalidStreamHandle);
+        }
+        let duration = decoder.total_duration();
+        let format = AudioFormat {
+            sample_rate: decoder.sample_rate(),
+            channels: decoder.channels(),
+        };
+        let stretch = StretchControl::new(options.tempo);
+        let effects = EffectControls::new(options.pan, options.mono, options.equalizer);
+        let stretched = TimeStretch::new(decoder, stretch.clone());
+        // Downmixed first, so the balance still moves the mixed signal.
+        let mixed = Downmix::new(stretched, effects.clone());
+        let processed = Equalizer::new(Balance::new(mixed, effects.clone()), effects.clone());
+
+        let sink = Sink::connect_new(self.stream_handle.mixer());
+        // Paused before anything is appended, or the first samples slip out.
+        if options.paused {
+            sink.pause();
+        }
+        sink.set_volume(options.volume);
+        sink.set_speed(options.speed);
+        let source: Box<dyn Source + Send> = match options.fade_in {
+            Some(duration) => Box::new(processed.fade_in(duration)),
+            None => Box::new(processed),
+        };
+        let capture = CaptureControl::new();
+        let meter = MeterControl::new(options.metering);
+        sink.append(Capture::new(
+            LevelMeter::new(source, meter.clone()),
+            capture.clone(),
+        ));
+
+        Ok(LoadedTrack {
+            sink: RodioSink {
+                sink,
+                stretch,
+                effects,
+                capture,
+                meter,
+            },
+            duration,
+            format: Some(format),
+        })
+    }
+
+    fn measure_loudness(source: &TrackSource) -> SoundPlayerResult<Option<f64>> {
+        Ok(loudness::measure(open_decoder(source)?))
+    }
+
+    fn list_devices(&self) -> SoundPlayerResult<Vec<String>> {
+        let devices = rodio::cpal::default_host().output_devices()?;
+        Ok(devices
+            .map(|device| device.name().unwrap_or_else(|_| "<unknown>".to_string()))
+            .collect())
+    }
+
+    fn device_name(&self) -> Option<String> {
+        self.device_name.clone()
+    }
+
+    /// Unhealthy once the audio thread reported the device gone, or when the
+    /// device picked is no longer among the outputs.
+    fn stream_healthy(&self) -> bool {
+        !self.stream_lost.load(Ordering::SeqCst)
+            && self
+                .device_name
+                .as_deref()
+                .is_none_or(|name| find_device(name).is_ok())
+    }
+
+    fn set_device(&mut self, name: &str) -> SoundPlayerResult<()> {
+        let device = find_device(name)?;
+        // A fresh flag, so forks still on the old stream keep their own.
+        self.stream_lost = Arc::new(AtomicBool::new(false));
+        self.stream_handle = Arc::new(open_stream(Some(device), &self.stream_lost)?);
+        self.device_name = Some(name.to_string());
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> SoundPlayerResult<()> {
+        let device = match &self.device_name {
+            Some(name) => find_device(name).ok(),
+            None => None,
+        };
+        if device.is_none() {
+            self.device_name = None;
+        }
+        self.stream_lost = Arc::new(AtomicBool::new(false));
+        self.stream_handle = Arc::new(open_stream(device, &self.stream_lost)?);
+        Ok(())
+    }
+
+    fn fork(&self) -> SoundPlayerResult<Self> {
+        Ok(Self {
+            stream_handle: self.stream_handle.clone(),
+            stream_lost: self.stream_lost.clone(),
+            device_name: self.device_name.clone(),
+        })
+    }
+}
+
+/// A rodio sink playing a single track through a `TimeStretch` and the effects in
+/// `EffectControls`.
+pub struct RodioSink {
+    sink: Sink,
+    stretch: Arc<StretchControl>,
+    effects: Arc<EffectControls>,
+    capture: Arc<CaptureControl>,
+    meter: Arc<MeterControl>,
+}
+
+impl AudioSink for RodioSink {
+    fn play(&self) {
+        self.sink.play()
+    }
+
+    fn pause(&self) {
+        self.sink.pause()
+    }
+
+    fn stop(&self) {
+        self.sink.stop()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    fn empty(&self) -> bool {
+        self.sink.empty()
+    }
+
+    fn volume(&self) -> f32 {
+        self.sink.volume()
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.sink.set_volume(volume)
+    }
+
+    fn set_speed(&self, speed: f32) {
+        self.sink.set_speed(speed)
+    }
+
+    fn set_tempo(&self, tempo: f32) {
+        self.stretch.set_tempo(tempo)
+    }
+
+    fn set_pan(&self, pan: f32) {
+        self.effects.set_pan(pan)
+    }
+
+    fn set_mono(&self, mono: bool) {
+        self.effects.set_mono(mono)
+    }
+
+    fn set_equalizer(&self, gains: EqualizerGains) {
+        self.effects.set_equalizer(gains)
+    }
+
+    fn try_seek(&self, position: Duration) -> Result<(), SeekError> {
+        self.sink.try_seek(position)
+    }
+
+    /// Taken from the stretcher rather than the sink, so it stays in track time
+    /// whatever the speed and tempo.
+    fn get_pos(&self) -> Duration {
+        self.stretch.position()
+    }
+
+    fn record(&self, sender: Option<CaptureSender>) {
+        self.capture.set(sender)
+    }
+
+    fn set_metering(&self, enabled: bool) {
+        self.meter.set_enabled(enabled)
+    }
+
+    /// Measured before the sink's volume is applied, so scaled by it here.
+    fn take_levels(&self) -> Option<Levels> {
+        self.meter
+            .take()
+            .map(|levels| levels.scaled(self.sink.volume()))
+    }
+}
+
+/// How much audio `RodioBackend::prepare` decodes ahead of time.
+const PREFETCH: Duration = Duration::from_secs(2);
+
+/// Longest track `TrackSource::Reversed` accepts. Reversing holds the whole
+/// track in memory, about 23 MB a minute for 48 kHz stereo.
+pub const MAX_REVERSE_DURATION: Duration = Duration::from_secs(120);
+
+/// Checks a track of `duration` against `MAX_REVERSE_DURATION`.
+pub(crate) fn check_reversible(path: &Path, duration: Option<Duration>) -> SoundPlayerResult<()> {
+    match duration {
+        Some(duration) if duration > MAX_REVERSE_DURATION => Err(too_long_to_reverse(path)),
+        _ => Ok(()),
+    }
+}
+
+fn too_long_to_reverse(path: &Path) -> SoundPlayerError {
+    SoundPlayerError::CannotReverse {
+        file: path.display().to_string(),
+        reason: format!("longer than {} seconds", MAX_REVERSE_DURATION.as_secs()),
+    }
+}
+
+/// Decodes `path` completely and returns its audio with the frames in reverse
+/// order. Stops decoding as soon as the track turns out to be too long.
+fn decode_reversed(path: &Path) -> SoundPlayerResult<SamplesBuffer> {
+    let file = open_audio_file(path)?;
+    let decoder = Decoder::try_from(file).map_err(|e| SoundPlayerError::DecodingError {
+        file: path.display().to_string(),
+        source: e,
+    })?;
+    check_reversible(path, decoder.total_duration())?;
+    let channels = decoder.channels().max(1);
+    let sample_rate = decoder.sample_rate();
+    let limit = MAX_REVERSE_DURATION.as_secs() as usize * sample_rate as usize * channels as usize;
+    let samples: Vec<Sample> = decoder.take(limit + 1).collect();
+    if samples.len() > limit {
+        return Err(too_long_to_reverse(path));
+    }
+    // Whole frames are reversed, so channels stay where they were.
+    let reversed: Vec<Sample> = samples
+        .chunks_exact(channels as usize)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    Ok(SamplesBuffer::new(channels, sample_rate, reversed))
+}
+
+/// A source whose opening samples were decoded up front, so starting it doesn't
+/// wait on the decoder.
+pub struct Prefetch<S> {
+    input: S,
+    head: VecDeque<Sample>,
+    /// Format of the samples in `head`; the input may have moved on to another.
+    head_channels: ChannelCount,
+    head_sample_rate: SampleRate,
+}
+
+impl<S: Source> Prefetch<S> {
+    fn new(mut input: S, length: Duration) -> Self {
+        let head_channels = input.channels();
+        let head_sample_rate = input.sample_rate();
+        let wanted = (length.as_secs_f64() * head_sample_rate as f64) as usize
+            * head_channels.max(1) as usize;
+        let mut head = VecDeque::with_capacity(wanted);
+        // Whole spans only, and only while the format stays the same, so the head
+        // can be reported as a single span.
+        while head.len() < wanted
+            && input.channels() == head_channels
+            && input.sample_rate() == head_sample_rate
+        {
+            let span = input.current_span_len().unwrap_or(wanted - head.len());
+            let before = head.len();
+            head.extend(input.by_ref().take(span));
+            if head.len() - before < span.max(1) {
+                break;
+            }
+        }
+        Self {
+            input,
+            head,
+            head_channels,
+            head_sample_rate,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Prefetch<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        self.head.pop_front().or_else(|| self.input.next())
+    }
+}
+
+impl<S: Source> Source for Prefetch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        match self.head.len() {
+            0 => self.input.current_span_len(),
+            len => Some(len),
+        }
+    }
+
+    fn channels(&self) -> ChannelCount {
+        match self.head.is_empty() {
+            true => self.input.channels(),
+            false => self.head_channels,
+        }
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        match self.head.is_empty() {
+            true => self.input.sample_rate(),
+            false => self.head_sample_rate,
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+        self.head.clear();
+        Ok(())
+    }
+}
+
+fn open_decoder(source: &TrackSource) -> SoundPlayerResult<Box<dyn Source + Send>> {
+    let decoded = match source {
+        TrackSource::File(path) => {
+            let file = open_audio_file(path)?;
+            Decoder::try_from(file).map(|decoder| Box::new(decoder) as Box<dyn Source + Send>)
+        }
+        TrackSource::Url(url) => {
+            let stream =
+                HttpStream::open(url).map_err(|reason| SoundPlayerError::NetworkError {
+                    url: url.clone(),
+                    reason,
+                })?;
+            let mut builder = Decoder::builder().with_seekable(false);
+            if let Some(mime_type) = &stream.content_type {
+                builder = builder.with_mime_type(mime_type);
+            }
+            if let Some(ext) = Path::new(url).extension().and_then(|ext| ext.to_str()) {
+                builder = builder.with_hint(ext);
+            }
+            builder
+                .with_data(stream)
+                .build()
+                .map(|decoder| Box::new(decoder) as Box<dyn Source + Send>)
+        }
+        TrackSource::Inline { data, format } => {
+            check_decodable(sniff(data), &source.to_string())?;
+            Decoder::builder()
+                .with_byte_len(data.len() as u64)
+                .with_seekable(true)
+                .with_hint(format)
+                .with_data(Cursor::new(data.clone()))
+                .build()
+                .map(|decoder| Box::new(decoder) as Box<dyn Source + Send>)
+        }
+        TrackSource::Reversed(path) => return Ok(Box::new(decode_reversed(path)?)),
+    };
+    decoded.map_err(|e| SoundPlayerError::DecodingError {
+        file: source.to_string(),
+        source: e,
+    })
+}
+
+/// Opens `path`, surfacing missing files and permission problems as their own
+/// variants.
+pub(crate) fn open_file(path: &Path) -> SoundPlayerResult<File> {
+    File::open(path).map_err(|e| file_error(path, e))
+}
+
+/// Opens `path` for decoding, rejecting it up front if its first bytes show a
+/// format this build can't decode.
+pub(crate) fn open_audio_file(path: &Path) -> SoundPlayerResult<File> {
+    let mut file = open_file(path)?;
+    let format = sniff_reader(&mut file).map_err(|e| file_error(path, e))?;
+    check_decodable(format, &path.display().to_string())?;
+    Ok(file)
+}
+
+/// Turns a recognised format without a decoder into an `UnsupportedFormat`
+/// naming it, rather than the bare decoding failure it would become. Audio
+/// that wasn't recognised is left for the decoder to try.
+fn check_decodable(format: Option<SniffedFormat>, file: &str) -> SoundPlayerResult<()> {
+    match format {
+        Some(format) if !format.decodable() => Err(SoundPlayerError::UnsupportedFormat {
+            file: file.to_string(),
+            ext: format.name().to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Maps an error from opening `path` to the matching `SoundPlayerError`.
+pub(crate) fn file_error(path: &Path, e: io::Error) -> SoundPlayerError {
+    let file = path.display().to_string();
+    match e.kind() {
+        io::ErrorKind::NotFound => SoundPlayerError::FileNotFound { file },
+        io::ErrorKind::PermissionDenied => SoundPlayerError::PermissionDenied { file },
+        _ => SoundPlayerError::FileOpenError { file, source: e },
+    }
+}
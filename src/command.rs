@@ -1,40 +1,840 @@
-use crate::order::Order;
+use crate::{
+    effects::{EQ_BANDS_HZ, EqualizerGains},
+    meter::MIN_LEVELS_INTERVAL,
+    order::Order,
+    sound_player::{FadeCurve, RepeatMode},
+};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
 
+/// Longest sleep timer accepted, a week.
+pub const MAX_SLEEP_TIMER_MINUTES: f64 = 7.0 * 24.0 * 60.0;
+
+/// A parsed order. Serializes as an object tagged with the canonical command
+/// name, e.g. `{"command":"seek","position":12.5}`; that form is for logs and
+/// tooling, while clients keep sending `Order`s.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "command", rename_all = "lowercase")]
 pub enum Command {
-    Play { song_name: String },
-    Stop,
+    Play {
+        song_name: String,
+        fade_in_ms: Option<u64>,
+    },
+    Stop {
+        fade_out_ms: Option<u64>,
+    },
+    PlayOverlay {
+        song_name: String,
+    },
+    PlayReverse {
+        song_name: String,
+    },
+    PlayFrom {
+        song_name: String,
+        position: f64,
+    },
+    /// Plays audio sent in the order itself; `format` is its file extension.
+    PlayInline {
+        data_base64: String,
+        format: String,
+    },
+    StopAll,
     Pause,
     Resume,
-    Seek { position: u64 },
-    Volume { level: f32 },
-    Speed { factor: f32 },
+    #[serde(rename = "toggleplay")]
+    TogglePause,
+    Seek {
+        position: f64,
+    },
+    SeekPercent {
+        percent: f64,
+    },
+    /// Zero goes to the very end.
+    SeekEnd {
+        offset_secs: f64,
+    },
+    #[serde(rename = "skip")]
+    SeekRelative {
+        delta: f64,
+    },
+    Restart,
+    LoopAb {
+        start: f64,
+        end: f64,
+    },
+    LoopClear,
+    /// With `clamp`, out-of-range levels are pulled into range instead of rejected.
+    Volume {
+        level: f32,
+        clamp: bool,
+    },
+    VolumeDb {
+        db: f32,
+    },
+    VolumeRamp {
+        level: f32,
+        ms: u64,
+    },
+    VolumeFade {
+        level: f32,
+        ms: u64,
+        curve: FadeCurve,
+    },
+    MasterVolume {
+        level: f32,
+    },
+    Speed {
+        factor: f32,
+        clamp: bool,
+    },
+    Tempo {
+        factor: f32,
+    },
+    Balance {
+        pan: f32,
+    },
+    Equalizer {
+        bands: EqualizerGains,
+    },
+    Mute,
+    Unmute,
+    Normalize {
+        enabled: bool,
+    },
+    /// Gain offset in dB remembered for one track; 0 forgets it.
+    SetTrackGain {
+        song_name: String,
+        gain: f32,
+    },
+    Mono {
+        enabled: bool,
+    },
+    GetPosition,
+    GetDuration,
+    TimeInfo,
+    CanSeek,
+    Status,
+    Enqueue {
+        song_name: String,
+    },
+    Next,
+    /// Unless `keep` is set, the entries before `index` are dropped.
+    JumpTo {
+        index: usize,
+        keep: bool,
+    },
+    Clear,
+    /// Zero disables crossfading.
+    Crossfade {
+        ms: u64,
+    },
+    /// Fade-out of the last track before the queue runs dry; zero disables it.
+    EndFade {
+        ms: u64,
+    },
+    GetQueue,
+    History,
+    PlayPrevious,
+    Dequeue {
+        index: usize,
+    },
+    MoveInQueue {
+        from: usize,
+        to: usize,
+    },
+    Repeat {
+        mode: RepeatMode,
+    },
+    RepeatCount {
+        n: u32,
+    },
+    Metadata,
+    AudioInfo,
+    Record {
+        path: String,
+    },
+    StopRecording,
+    ListDevices,
+    DeviceStatus,
+    CycleDevice,
+    SetDevice {
+        name: String,
+    },
+    Shuffle {
+        seed: Option<u64>,
+    },
+    SleepTimer {
+        minutes: f64,
+    },
+    SleepTimerCancel,
+    LoadPlaylist {
+        path: String,
+    },
+    /// Without a path the configured state file is used.
+    SaveState {
+        path: Option<String>,
+    },
+    RestoreState {
+        path: Option<String>,
+    },
+    Subscribe,
+    /// Sends a levels event every `interval_ms`, or `DEFAULT_LEVELS_INTERVAL`.
+    SubscribeLevels {
+        interval_ms: Option<u64>,
+    },
+    UnsubscribeLevels,
+    Unsubscribe,
+    Help,
+    Ping,
+    Metrics,
+    SetLogLevel {
+        level: LevelFilter,
+    },
 }
 
+#[derive(Debug)]
 pub enum CommandParseError {
     InvalidParameters,
     UnknownCommand,
 }
 
+impl Command {
+    /// Whether running the command can change what a status snapshot shows.
+    /// Queries and subscription management don't.
+    pub fn changes_state(&self) -> bool {
+        !matches!(
+            self,
+            Command::GetPosition
+                | Command::GetDuration
+                | Command::TimeInfo
+                | Command::CanSeek
+                | Command::Status
+                | Command::GetQueue
+                | Command::Crossfade { .. }
+                | Command::EndFade { .. }
+                | Command::History
+                | Command::Metadata
+                | Command::AudioInfo
+                | Command::Record { .. }
+                | Command::StopRecording
+                | Command::ListDevices
+                | Command::DeviceStatus
+                | Command::SaveState { .. }
+                | Command::Subscribe
+                | Command::SubscribeLevels { .. }
+                | Command::UnsubscribeLevels
+                | Command::Unsubscribe
+                | Command::Help
+                | Command::Ping
+                | Command::Metrics
+                | Command::SetLogLevel { .. }
+        )
+    }
+}
+
+impl CommandParseError {
+    /// Stable, machine-readable identifier for the error variant.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CommandParseError::InvalidParameters => "invalid_parameters",
+            CommandParseError::UnknownCommand => "unknown_command",
+        }
+    }
+}
+
+/// Parses an optional trailing parameter; present-but-malformed is an error.
+fn parse_optional<T: FromStr>(param: Option<&String>) -> Result<Option<T>, CommandParseError> {
+    param
+        .map(|value| value.parse::<T>())
+        .transpose()
+        .map_err(|_| CommandParseError::InvalidParameters)
+}
+
+/// Parses the optional `clamp` keyword that follows a volume or speed value.
+fn parse_clamp(param: Option<&String>) -> Result<bool, CommandParseError> {
+    match param {
+        None => Ok(false),
+        Some(value) if value.eq_ignore_ascii_case("clamp") => Ok(true),
+        Some(_) => Err(CommandParseError::InvalidParameters),
+    }
+}
+
+/// Parses an `on`/`off` switch; `true` and `false` work too.
+fn parse_switch(param: Option<&String>) -> Result<bool, CommandParseError> {
+    match param.map(|value| value.to_lowercase()).as_deref() {
+        Some("on" | "true") => Ok(true),
+        Some("off" | "false") => Ok(false),
+        _ => Err(CommandParseError::InvalidParameters),
+    }
+}
+
+/// Name, accepted aliases and parameter signature of an order.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub params: &'static str,
+    pub description: &'static str,
+}
+
+/// Every order the player understands. Parsing looks names and aliases up here
+/// first, so a command that's missing from this list can't be used. Aliases
+/// include the short forms meant for typing orders by hand.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "play",
+        aliases: &["p", "pl"],
+        params: "<song> [fade_in_ms]",
+        description: "Play a file or http:// URL; a directory or wildcard queues every match",
+    },
+    CommandSpec {
+        name: "stop",
+        aliases: &["s"],
+        params: "[fade_out_ms]",
+        description: "Stop the current track, optionally fading out",
+    },
+    CommandSpec {
+        name: "playoverlay",
+        aliases: &["overlay"],
+        params: "<song>",
+        description: "Play a sound on top of the current track",
+    },
+    CommandSpec {
+        name: "playfrom",
+        aliases: &["from"],
+        params: "<song> <secs>",
+        description: "Play a track starting at a position",
+    },
+    CommandSpec {
+        name: "playreverse",
+        aliases: &["reverse"],
+        params: "<song>",
+        description: "Play a file backwards (up to two minutes long)",
+    },
+    CommandSpec {
+        name: "playinline",
+        aliases: &[],
+        params: "<base64> <format>",
+        description: "Play audio sent in the order itself",
+    },
+    CommandSpec {
+        name: "stopall",
+        aliases: &[],
+        params: "",
+        description: "Stop all overlay sounds",
+    },
+    CommandSpec {
+        name: "pause",
+        aliases: &["pa"],
+        params: "",
+        description: "Pause playback",
+    },
+    CommandSpec {
+        name: "resume",
+        aliases: &["res"],
+        params: "",
+        description: "Resume paused playback",
+    },
+    CommandSpec {
+        name: "toggleplay",
+        aliases: &["t", "toggle"],
+        params: "",
+        description: "Pause if playing, resume if paused",
+    },
+    CommandSpec {
+        name: "seek",
+        aliases: &["sk"],
+        params: "<secs>",
+        description: "Jump to an absolute position",
+    },
+    CommandSpec {
+        name: "seekpercent",
+        aliases: &["seekpct"],
+        params: "<0-100>",
+        description: "Jump to a fraction of the track's length",
+    },
+    CommandSpec {
+        name: "seekend",
+        aliases: &["outro"],
+        params: "[offset_secs]",
+        description: "Jump to the given time before the end of the track",
+    },
+    CommandSpec {
+        name: "skip",
+        aliases: &[],
+        params: "<delta_secs>",
+        description: "Jump forward, or back when negative",
+    },
+    CommandSpec {
+        name: "restart",
+        aliases: &["replay"],
+        params: "",
+        description: "Start the current track over",
+    },
+    CommandSpec {
+        name: "loopab",
+        aliases: &["loop"],
+        params: "<start_secs> <end_secs>",
+        description: "Keep looping a region of the current track",
+    },
+    CommandSpec {
+        name: "loopclear",
+        aliases: &[],
+        params: "",
+        description: "Stop looping a region",
+    },
+    CommandSpec {
+        name: "volume",
+        aliases: &["vol", "v"],
+        params: "<level> [clamp]",
+        description: "Set the volume; 1.0 is unity gain",
+    },
+    CommandSpec {
+        name: "volumedb",
+        aliases: &[],
+        params: "<db>",
+        description: "Set the volume in decibels",
+    },
+    CommandSpec {
+        name: "volumeramp",
+        aliases: &["ramp"],
+        params: "<level> <ms>",
+        description: "Glide to a volume over the given time",
+    },
+    CommandSpec {
+        name: "volumefade",
+        aliases: &["fade"],
+        params: "<level> <ms> [linear|exponential|logarithmic]",
+        description: "Glide to a volume along an easing curve (linear by default)",
+    },
+    CommandSpec {
+        name: "mastervolume",
+        aliases: &["master"],
+        params: "<level>",
+        description: "Set the gain applied on top of every track's volume",
+    },
+    CommandSpec {
+        name: "speed",
+        aliases: &["spd"],
+        params: "<factor> [clamp]",
+        description: "Change playback speed, shifting pitch",
+    },
+    CommandSpec {
+        name: "tempo",
+        aliases: &[],
+        params: "<factor>",
+        description: "Change playback speed, keeping pitch",
+    },
+    CommandSpec {
+        name: "balance",
+        aliases: &["pan"],
+        params: "<-1.0..1.0>",
+        description: "Shift stereo output left or right",
+    },
+    CommandSpec {
+        name: "equalizer",
+        aliases: &["eq"],
+        params: "<db> <db> <db> <db> <db>",
+        description: "Set the five equalizer bands",
+    },
+    CommandSpec {
+        name: "mute",
+        aliases: &[],
+        params: "",
+        description: "Silence output, remembering the volume",
+    },
+    CommandSpec {
+        name: "unmute",
+        aliases: &[],
+        params: "",
+        description: "Restore the volume from before mute",
+    },
+    CommandSpec {
+        name: "normalize",
+        aliases: &["loudness"],
+        params: "<on|off>",
+        description: "Bring tracks to the same perceived loudness",
+    },
+    CommandSpec {
+        name: "settrackgain",
+        aliases: &["trackgain"],
+        params: "<song> <dB>",
+        description: "Remember a gain for one track, applied whenever it plays; 0 forgets it",
+    },
+    CommandSpec {
+        name: "mono",
+        aliases: &["downmix"],
+        params: "<on|off>",
+        description: "Play the average of all channels on each of them",
+    },
+    CommandSpec {
+        name: "getposition",
+        aliases: &["pos", "position"],
+        params: "",
+        description: "Position in the current track",
+    },
+    CommandSpec {
+        name: "getduration",
+        aliases: &["dur", "duration"],
+        params: "",
+        description: "Length of the current track",
+    },
+    CommandSpec {
+        name: "timeinfo",
+        aliases: &["time"],
+        params: "",
+        description: "Elapsed and remaining time of the current track",
+    },
+    CommandSpec {
+        name: "canseek",
+        aliases: &["seekable"],
+        params: "",
+        description: "Whether the current track can seek backwards as well as forwards",
+    },
+    CommandSpec {
+        name: "status",
+        aliases: &["st"],
+        params: "",
+        description: "Snapshot of the player state",
+    },
+    CommandSpec {
+        name: "enqueue",
+        aliases: &["q", "add"],
+        params: "<song>",
+        description: "Add a track to the queue",
+    },
+    CommandSpec {
+        name: "next",
+        aliases: &["n"],
+        params: "",
+        description: "Play the next queued track",
+    },
+    CommandSpec {
+        name: "jumpto",
+        aliases: &["jump"],
+        params: "<index> [keep]",
+        description: "Play the queue entry at a zero-based index",
+    },
+    CommandSpec {
+        name: "clear",
+        aliases: &[],
+        params: "",
+        description: "Empty the queue",
+    },
+    CommandSpec {
+        name: "crossfade",
+        aliases: &["xfade"],
+        params: "<ms>",
+        description: "Overlap consecutive queued tracks; 0 disables",
+    },
+    CommandSpec {
+        name: "endfade",
+        aliases: &["fadeatend"],
+        params: "<ms>",
+        description: "Fade out the last track when the queue runs out; 0 disables",
+    },
+    CommandSpec {
+        name: "getqueue",
+        aliases: &["queue"],
+        params: "",
+        description: "The current track and the queued ones",
+    },
+    CommandSpec {
+        name: "dequeue",
+        aliases: &["remove", "rm"],
+        params: "<index>",
+        description: "Remove the queue entry at a zero-based index",
+    },
+    CommandSpec {
+        name: "moveinqueue",
+        aliases: &["move", "mv"],
+        params: "<from> <to>",
+        description: "Move a queue entry to another position",
+    },
+    CommandSpec {
+        name: "history",
+        aliases: &[],
+        params: "",
+        description: "Recently played tracks, oldest first",
+    },
+    CommandSpec {
+        name: "playprevious",
+        aliases: &["previous", "prev", "back"],
+        params: "",
+        description: "Play the track before the current one",
+    },
+    CommandSpec {
+        name: "repeat",
+        aliases: &[],
+        params: "<off|one|all>",
+        description: "Set the repeat mode",
+    },
+    CommandSpec {
+        name: "repeatcount",
+        aliases: &["times"],
+        params: "<n>",
+        description: "Play the current track n times in total",
+    },
+    CommandSpec {
+        name: "shuffle",
+        aliases: &[],
+        params: "[seed]",
+        description: "Shuffle the queue",
+    },
+    CommandSpec {
+        name: "metadata",
+        aliases: &["meta", "tags"],
+        params: "",
+        description: "Tags of the current track",
+    },
+    CommandSpec {
+        name: "audioinfo",
+        aliases: &["info"],
+        params: "",
+        description: "Sample rate, channels and codec of the current track",
+    },
+    CommandSpec {
+        name: "record",
+        aliases: &["rec"],
+        params: "<wav file>",
+        description: "Write the rest of the current track to a WAV file",
+    },
+    CommandSpec {
+        name: "stoprecording",
+        aliases: &["stoprec"],
+        params: "",
+        description: "Stop recording and finalize the file",
+    },
+    CommandSpec {
+        name: "listdevices",
+        aliases: &["devices"],
+        params: "",
+        description: "Names of the output devices",
+    },
+    CommandSpec {
+        name: "devicestatus",
+        aliases: &["outputstatus"],
+        params: "",
+        description: "Current output device and whether its stream still works",
+    },
+    CommandSpec {
+        name: "setdevice",
+        aliases: &["device"],
+        params: "<name>",
+        description: "Switch to another output device",
+    },
+    CommandSpec {
+        name: "cycledevice",
+        aliases: &["nextdevice"],
+        params: "",
+        description: "Switch to the next preferred (or available) output device",
+    },
+    CommandSpec {
+        name: "sleeptimer",
+        aliases: &["sleep"],
+        params: "<minutes>",
+        description: "Fade out and stop after a delay",
+    },
+    CommandSpec {
+        name: "sleeptimercancel",
+        aliases: &["cancelsleep"],
+        params: "",
+        description: "Cancel the sleep timer",
+    },
+    CommandSpec {
+        name: "loadplaylist",
+        aliases: &["playlist"],
+        params: "<m3u file>",
+        description: "Queue a playlist and start it",
+    },
+    CommandSpec {
+        name: "savestate",
+        aliases: &[],
+        params: "[path]",
+        description: "Save the session to a file",
+    },
+    CommandSpec {
+        name: "restorestate",
+        aliases: &["loadstate"],
+        params: "[path]",
+        description: "Restore a saved session",
+    },
+    CommandSpec {
+        name: "subscribe",
+        aliases: &[],
+        params: "",
+        description: "Receive a state_changed event after every change",
+    },
+    CommandSpec {
+        name: "unsubscribe",
+        aliases: &[],
+        params: "",
+        description: "Stop state_changed events",
+    },
+    CommandSpec {
+        name: "subscribelevels",
+        aliases: &["levels"],
+        params: "[interval_ms]",
+        description: "Receive peak and RMS levels of the current track periodically",
+    },
+    CommandSpec {
+        name: "unsubscribelevels",
+        aliases: &[],
+        params: "",
+        description: "Stop levels events",
+    },
+    CommandSpec {
+        name: "ping",
+        aliases: &[],
+        params: "",
+        description: "Liveness check; reports version and uptime",
+    },
+    CommandSpec {
+        name: "metrics",
+        aliases: &["stats"],
+        params: "",
+        description: "Counts of orders handled and errors, by kind",
+    },
+    CommandSpec {
+        name: "setloglevel",
+        aliases: &["loglevel"],
+        params: "<off|error|warn|info|debug|trace>",
+        description: "Change how much is logged, up to what RUST_LOG allows",
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["h", "?", "commands"],
+        params: "",
+        description: "List the supported commands",
+    },
+];
+
+/// The spec whose name or alias matches `name`, ignoring case.
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| {
+        spec.name.eq_ignore_ascii_case(name)
+            || spec
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Largest edit distance at which an unknown name is still taken for a typo.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// The known command name or alias closest to `name`, if it's within
+/// `MAX_SUGGESTION_DISTANCE` edits.
+pub fn suggest_command(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    COMMANDS
+        .iter()
+        .flat_map(|spec| std::iter::once(&spec.name).chain(spec.aliases))
+        // Short forms are within a couple of edits of almost anything.
+        .filter(|candidate| candidate.chars().count() > MAX_SUGGESTION_DISTANCE)
+        .map(|candidate| (edit_distance(&name, candidate), *candidate))
+        .filter(|&(distance, _)| {
+            distance <= MAX_SUGGESTION_DISTANCE && distance < name.chars().count()
+        })
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 impl TryFrom<&Order> for Command {
     type Error = CommandParseError;
 
     fn try_from(order: &Order) -> Result<Self, Self::Error> {
-        match order.command_name.to_lowercase().as_str() {
+        let spec = find_command(&order.command_name).ok_or(CommandParseError::UnknownCommand)?;
+        match spec.name {
             "play" => {
-                if let Some(song_name) = order.parameters.get(0) {
+                if let Some(song_name) = order.parameters.first() {
+                    let fade_in_ms = parse_optional(order.parameters.get(1))?;
                     Ok(Command::Play {
                         song_name: song_name.clone(),
+                        fade_in_ms,
                     })
                 } else {
                     Err(CommandParseError::InvalidParameters)
                 }
             }
-            "stop" => Ok(Command::Stop),
+            "stop" => {
+                let fade_out_ms = parse_optional(order.parameters.first())?;
+                Ok(Command::Stop { fade_out_ms })
+            }
+            "playoverlay" => {
+                if let Some(song_name) = order.parameters.first() {
+                    Ok(Command::PlayOverlay {
+                        song_name: song_name.clone(),
+                    })
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "playfrom" => {
+                if let (Some(song_name), Some(pos_str)) =
+                    (order.parameters.first(), order.parameters.get(1))
+                {
+                    if let Ok(position) = pos_str.parse::<f64>()
+                        && position.is_finite()
+                        && position >= 0.0
+                    {
+                        Ok(Command::PlayFrom {
+                            song_name: song_name.clone(),
+                            position,
+                        })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "playreverse" => {
+                if let Some(song_name) = order.parameters.first() {
+                    Ok(Command::PlayReverse {
+                        song_name: song_name.clone(),
+                    })
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "playinline" => {
+                if let [data_base64, format] = order.parameters.as_slice() {
+                    Ok(Command::PlayInline {
+                        data_base64: data_base64.clone(),
+                        format: format.clone(),
+                    })
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "stopall" => Ok(Command::StopAll),
             "pause" => Ok(Command::Pause),
             "resume" => Ok(Command::Resume),
+            "toggleplay" => Ok(Command::TogglePause),
             "seek" => {
-                if let Some(pos_str) = order.parameters.get(0) {
-                    if let Ok(position) = pos_str.parse::<u64>() {
+                if let Some(pos_str) = order.parameters.first() {
+                    if let Ok(position) = pos_str.parse::<f64>()
+                        && position.is_finite()
+                        && position >= 0.0
+                    {
                         Ok(Command::Seek { position })
                     } else {
                         Err(CommandParseError::InvalidParameters)
@@ -43,10 +843,111 @@ impl TryFrom<&Order> for Command {
                     Err(CommandParseError::InvalidParameters)
                 }
             }
+            "seekpercent" => {
+                if let Some(percent_str) = order.parameters.first() {
+                    if let Ok(percent) = percent_str.trim_end_matches('%').parse::<f64>() {
+                        Ok(Command::SeekPercent { percent })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "seekend" => {
+                let offset_secs: f64 = parse_optional(order.parameters.first())?.unwrap_or(0.0);
+                if offset_secs >= 0.0 && offset_secs.is_finite() {
+                    Ok(Command::SeekEnd { offset_secs })
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "skip" => {
+                if let Some(delta_str) = order.parameters.first() {
+                    if let Ok(delta) = delta_str.parse::<f64>()
+                        && delta.is_finite()
+                    {
+                        Ok(Command::SeekRelative { delta })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "restart" => Ok(Command::Restart),
+            "loopab" => {
+                if let (Some(start_str), Some(end_str)) =
+                    (order.parameters.first(), order.parameters.get(1))
+                {
+                    if let (Ok(start), Ok(end)) = (start_str.parse::<f64>(), end_str.parse::<f64>())
+                        && Duration::try_from_secs_f64(start).is_ok()
+                        && Duration::try_from_secs_f64(end).is_ok()
+                        && end > start
+                    {
+                        Ok(Command::LoopAb { start, end })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "loopclear" => Ok(Command::LoopClear),
             "volume" => {
-                if let Some(level_str) = order.parameters.get(0) {
+                if let Some(level_str) = order.parameters.first() {
+                    if let Ok(level) = level_str.parse::<f32>() {
+                        let clamp = parse_clamp(order.parameters.get(1))?;
+                        Ok(Command::Volume { level, clamp })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "volumedb" => {
+                if let Some(db_str) = order.parameters.first() {
+                    if let Ok(db) = db_str.parse::<f32>() {
+                        Ok(Command::VolumeDb { db })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "mastervolume" => {
+                if let Some(level_str) = order.parameters.first() {
                     if let Ok(level) = level_str.parse::<f32>() {
-                        Ok(Command::Volume { level })
+                        Ok(Command::MasterVolume { level })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "volumeramp" => {
+                if let (Some(level_str), Some(ms_str)) =
+                    (order.parameters.first(), order.parameters.get(1))
+                {
+                    if let (Ok(level), Ok(ms)) = (level_str.parse::<f32>(), ms_str.parse::<u64>()) {
+                        Ok(Command::VolumeRamp { level, ms })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "volumefade" => {
+                if let (Some(level_str), Some(ms_str)) =
+                    (order.parameters.first(), order.parameters.get(1))
+                {
+                    let curve = parse_optional(order.parameters.get(2))?.unwrap_or_default();
+                    if let (Ok(level), Ok(ms)) = (level_str.parse::<f32>(), ms_str.parse::<u64>()) {
+                        Ok(Command::VolumeFade { level, ms, curve })
                     } else {
                         Err(CommandParseError::InvalidParameters)
                     }
@@ -55,9 +956,258 @@ impl TryFrom<&Order> for Command {
                 }
             }
             "speed" => {
-                if let Some(factor_str) = order.parameters.get(0) {
+                if let Some(factor_str) = order.parameters.first() {
                     if let Ok(factor) = factor_str.parse::<f32>() {
-                        Ok(Command::Speed { factor })
+                        let clamp = parse_clamp(order.parameters.get(1))?;
+                        Ok(Command::Speed { factor, clamp })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "tempo" => {
+                if let Some(factor_str) = order.parameters.first() {
+                    if let Ok(factor) = factor_str.parse::<f32>() {
+                        Ok(Command::Tempo { factor })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "balance" => {
+                if let Some(pan_str) = order.parameters.first() {
+                    if let Ok(pan) = pan_str.parse::<f32>() {
+                        Ok(Command::Balance { pan })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "equalizer" => {
+                let mut bands = EqualizerGains::default();
+                if order.parameters.len() != EQ_BANDS_HZ.len() {
+                    return Err(CommandParseError::InvalidParameters);
+                }
+                for (band, gain_str) in bands.iter_mut().zip(&order.parameters) {
+                    match gain_str.parse::<f32>() {
+                        Ok(gain) if gain.is_finite() => *band = gain,
+                        _ => return Err(CommandParseError::InvalidParameters),
+                    }
+                }
+                Ok(Command::Equalizer { bands })
+            }
+            "mute" => Ok(Command::Mute),
+            "unmute" => Ok(Command::Unmute),
+            "normalize" => {
+                parse_switch(order.parameters.first()).map(|enabled| Command::Normalize { enabled })
+            }
+            "settrackgain" => {
+                if let (Some(song_name), Some(gain_str)) =
+                    (order.parameters.first(), order.parameters.get(1))
+                {
+                    if let Ok(gain) = gain_str.parse::<f32>() {
+                        Ok(Command::SetTrackGain {
+                            song_name: song_name.clone(),
+                            gain,
+                        })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "mono" => {
+                parse_switch(order.parameters.first()).map(|enabled| Command::Mono { enabled })
+            }
+            "getposition" => Ok(Command::GetPosition),
+            "getduration" => Ok(Command::GetDuration),
+            "timeinfo" => Ok(Command::TimeInfo),
+            "canseek" => Ok(Command::CanSeek),
+            "status" => Ok(Command::Status),
+            "enqueue" => {
+                if let Some(song_name) = order.parameters.first() {
+                    Ok(Command::Enqueue {
+                        song_name: song_name.clone(),
+                    })
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "next" => Ok(Command::Next),
+            "jumpto" => {
+                if let Some(index_str) = order.parameters.first() {
+                    if let Ok(index) = index_str.parse::<usize>() {
+                        let keep = match order.parameters.get(1) {
+                            None => false,
+                            Some(flag) if flag.eq_ignore_ascii_case("keep") => true,
+                            Some(_) => return Err(CommandParseError::InvalidParameters),
+                        };
+                        Ok(Command::JumpTo { index, keep })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "clear" => Ok(Command::Clear),
+            "getqueue" => Ok(Command::GetQueue),
+            "crossfade" => {
+                if let Some(ms_str) = order.parameters.first() {
+                    if let Ok(ms) = ms_str.parse::<u64>() {
+                        Ok(Command::Crossfade { ms })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "endfade" => {
+                if let Some(ms_str) = order.parameters.first() {
+                    if let Ok(ms) = ms_str.parse::<u64>() {
+                        Ok(Command::EndFade { ms })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "history" => Ok(Command::History),
+            "playprevious" => Ok(Command::PlayPrevious),
+            "dequeue" => {
+                if let Some(index_str) = order.parameters.first() {
+                    if let Ok(index) = index_str.parse::<usize>() {
+                        Ok(Command::Dequeue { index })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "moveinqueue" => {
+                if let (Some(from_str), Some(to_str)) =
+                    (order.parameters.first(), order.parameters.get(1))
+                {
+                    if let (Ok(from), Ok(to)) = (from_str.parse::<usize>(), to_str.parse::<usize>())
+                    {
+                        Ok(Command::MoveInQueue { from, to })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "repeat" => {
+                if let Some(mode_str) = order.parameters.first() {
+                    if let Ok(mode) = mode_str.parse::<RepeatMode>() {
+                        Ok(Command::Repeat { mode })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "repeatcount" => {
+                if let Some(n_str) = order.parameters.first() {
+                    if let Ok(n) = n_str.parse::<u32>() {
+                        Ok(Command::RepeatCount { n })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "shuffle" => match order.parameters.first() {
+                Some(seed_str) => {
+                    if let Ok(seed) = seed_str.parse::<u64>() {
+                        Ok(Command::Shuffle { seed: Some(seed) })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                }
+                None => Ok(Command::Shuffle { seed: None }),
+            },
+            "metadata" => Ok(Command::Metadata),
+            "audioinfo" => Ok(Command::AudioInfo),
+            "record" => {
+                if let Some(path) = order.parameters.first() {
+                    Ok(Command::Record { path: path.clone() })
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "stoprecording" => Ok(Command::StopRecording),
+            "listdevices" => Ok(Command::ListDevices),
+            "devicestatus" => Ok(Command::DeviceStatus),
+            "cycledevice" => Ok(Command::CycleDevice),
+            "setdevice" => {
+                if order.parameters.is_empty() {
+                    Err(CommandParseError::InvalidParameters)
+                } else {
+                    // Device names commonly contain spaces.
+                    Ok(Command::SetDevice {
+                        name: order.parameters.join(" "),
+                    })
+                }
+            }
+            "sleeptimer" => {
+                if let Some(minutes_str) = order.parameters.first() {
+                    if let Ok(minutes) = minutes_str.parse::<f64>()
+                        && minutes > 0.0
+                        && minutes <= MAX_SLEEP_TIMER_MINUTES
+                    {
+                        Ok(Command::SleepTimer { minutes })
+                    } else {
+                        Err(CommandParseError::InvalidParameters)
+                    }
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "sleeptimercancel" => Ok(Command::SleepTimerCancel),
+            "loadplaylist" => {
+                if let Some(path) = order.parameters.first() {
+                    Ok(Command::LoadPlaylist { path: path.clone() })
+                } else {
+                    Err(CommandParseError::InvalidParameters)
+                }
+            }
+            "savestate" => Ok(Command::SaveState {
+                path: order.parameters.first().cloned(),
+            }),
+            "restorestate" => Ok(Command::RestoreState {
+                path: order.parameters.first().cloned(),
+            }),
+            "subscribe" => Ok(Command::Subscribe),
+            "unsubscribe" => Ok(Command::Unsubscribe),
+            "subscribelevels" => {
+                let interval_ms: Option<u64> = parse_optional(order.parameters.first())?;
+                if interval_ms.is_some_and(|ms| ms < MIN_LEVELS_INTERVAL.as_millis() as u64) {
+                    return Err(CommandParseError::InvalidParameters);
+                }
+                Ok(Command::SubscribeLevels { interval_ms })
+            }
+            "unsubscribelevels" => Ok(Command::UnsubscribeLevels),
+            "help" => Ok(Command::Help),
+            "ping" => Ok(Command::Ping),
+            "metrics" => Ok(Command::Metrics),
+            "setloglevel" => {
+                if let Some(level_str) = order.parameters.first() {
+                    if let Ok(level) = level_str.parse::<LevelFilter>() {
+                        Ok(Command::SetLogLevel { level })
                     } else {
                         Err(CommandParseError::InvalidParameters)
                     }
@@ -69,3 +1219,118 @@ impl TryFrom<&Order> for Command {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parameters that parse for the command named `name`; most of the optional
+    /// ones are given so they're round-tripped too.
+    fn sample_parameters(name: &str) -> &'static [&'static str] {
+        match name {
+            "play" => &["cone.mp3", "500"],
+            "stop" => &["200"],
+            "playoverlay" | "playreverse" | "enqueue" => &["cone.mp3"],
+            "playfrom" => &["cone.mp3", "1.5"],
+            "playinline" => &["UklGRg==", "wav"],
+            "seek" => &["1.5"],
+            "seekpercent" => &["50"],
+            "seekend" => &["2"],
+            "skip" => &["-5"],
+            "loopab" => &["1", "2.5"],
+            "volume" => &["0.5", "clamp"],
+            "volumedb" => &["-6"],
+            "volumeramp" => &["0.5", "200"],
+            "volumefade" => &["0.5", "200", "exponential"],
+            "mastervolume" => &["0.75"],
+            "speed" => &["1.5"],
+            "tempo" => &["1.25"],
+            "balance" => &["-0.5"],
+            "equalizer" => &["1", "-2", "3", "-4", "5"],
+            "jumpto" => &["2", "keep"],
+            "normalize" | "mono" => &["on"],
+            "settrackgain" => &["cone.mp3", "-3"],
+            "crossfade" | "endfade" => &["500"],
+            "dequeue" => &["1"],
+            "moveinqueue" => &["0", "2"],
+            "repeat" => &["all"],
+            "repeatcount" => &["3"],
+            "shuffle" => &["42"],
+            "record" => &["out.wav"],
+            "setdevice" => &["default"],
+            "sleeptimer" => &["30"],
+            "loadplaylist" => &["list.m3u"],
+            "savestate" | "restorestate" => &["state.json"],
+            "subscribelevels" => &["100"],
+            "setloglevel" => &["debug"],
+            _ => &[],
+        }
+    }
+
+    #[test]
+    fn every_command_round_trips_through_serde() {
+        for spec in COMMANDS {
+            let order = Order {
+                command_name: spec.name.to_string(),
+                parameters: sample_parameters(spec.name)
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect(),
+                id: None,
+                player_id: None,
+            };
+            let command = Command::try_from(&order)
+                .unwrap_or_else(|e| panic!("{} {:?}: {:?}", spec.name, order.parameters, e));
+
+            let json = serde_json::to_value(&command).unwrap();
+            let parsed: Command = serde_json::from_value(json.clone())
+                .unwrap_or_else(|e| panic!("{}: {} from {}", spec.name, e, json));
+            assert_eq!(
+                serde_json::to_value(&parsed).unwrap(),
+                json,
+                "{}",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn orders_round_trip_through_serde() {
+        let json = r#"{"command_name":"seek","parameters":["12.5"],"id":"7"}"#;
+        let order: Order = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&order).unwrap(), json);
+    }
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("pause", "puase"), 2);
+        assert_eq!(edit_distance("pausé", "pause"), 1);
+    }
+
+    #[test]
+    fn typos_suggest_the_closest_command() {
+        assert_eq!(suggest_command("volum"), Some("volume"));
+        assert_eq!(suggest_command("shufle"), Some("shuffle"));
+        assert_eq!(suggest_command("enqeue"), Some("enqueue"));
+        assert_eq!(suggest_command("STOPP"), Some("stop"));
+    }
+
+    #[test]
+    fn far_off_names_get_no_suggestion() {
+        assert_eq!(suggest_command("xyzzy"), None);
+        assert_eq!(suggest_command("launchrockets"), None);
+        assert_eq!(suggest_command(""), None);
+    }
+
+    #[test]
+    fn short_names_are_not_taken_for_typos() {
+        // Aliases like "h" are within two edits of any short name, and so is
+        // a command mostly made of new letters; neither counts as a typo.
+        assert_eq!(suggest_command("x"), None);
+        assert_eq!(suggest_command("zz"), None);
+    }
+}
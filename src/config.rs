@@ -0,0 +1,516 @@
+use crate::config_file;
+use crate::loudness::DEFAULT_TARGET_LUFS;
+use crate::sound_player::{
+    DEFAULT_BACKGROUND_LOAD_BYTES, DEFAULT_HISTORY_LEN, DEFAULT_MAX_INLINE_BYTES,
+    DEFAULT_VOLUME_RAMP, MAX_SPEED, MAX_VOLUME, MIN_SPEED, PlayerOptions, ResumeFinished,
+    expand_tilde,
+};
+use std::env;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+use tungstenite::http::{HeaderValue, Uri};
+
+const DEFAULT_SERVER_URL: &str = "ws://127.0.0.1:9001";
+const SERVER_ENV: &str = "SOUND_PLAYER_SERVER";
+const TOKEN_ENV: &str = "SOUND_PLAYER_TOKEN";
+const BASE_DIR_ENV: &str = "SOUND_PLAYER_BASE_DIR";
+
+const DEFAULT_PING_INTERVAL_SECS: u64 = 15;
+const DEFAULT_PONG_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_QUIT_KEYWORDS: &[&str] = &["quit", "exit"];
+
+pub const USAGE: &str = "Usage: sound_player [--config <file.toml>] [--server <ws://host:port> | --listen <addr:port>] [--token <token>] \
+[--ping-interval <secs, 0 disables>] [--pong-timeout <secs>] [--base-dir <dir>] [--state-file <path>] [--track-gains-file <path>] [--max-inline-bytes <bytes>] [--background-load-bytes <bytes, 0 disables>] [--history-len <tracks>] [--volume-ramp-ms <ms>] [--end-fade-ms <ms>] [--max-speed <factor>] [--default-volume <0.0-3.0>] [--target-lufs <-70-0>] [--resume-finished <restart|report>] [--quit-keyword <word>]... [--preferred-device <name>]... [--autoplay <file or playlist>] [--silent[=false]] [--log-json[=false]]";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unknown argument: {0}")]
+    UnknownArgument(String),
+
+    #[error("Missing value for {0}")]
+    MissingValue(String),
+
+    #[error("Invalid value '{value}' for {flag}")]
+    InvalidValue { flag: String, value: String },
+
+    #[error("Invalid server URL '{url}': {reason}")]
+    InvalidServerUrl { url: String, reason: String },
+
+    #[error("Invalid auth token: must be printable ASCII")]
+    InvalidToken,
+
+    #[error("Cannot read config file {path}: {reason}")]
+    ConfigFile { path: String, reason: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server_url: String,
+    /// Accept a controller connection on this address instead of dialling
+    /// `server_url`.
+    pub listen: Option<SocketAddr>,
+    /// Sent to the server in client mode; required from clients in listen mode.
+    pub token: Option<String>,
+    /// How often to ping the server; `None` disables the heartbeat.
+    pub ping_interval: Option<Duration>,
+    /// How long to wait for a pong before treating the connection as dead.
+    pub pong_timeout: Duration,
+    /// Simulate playback instead of opening an audio device.
+    pub silent: bool,
+    /// Write one JSON object per log line instead of human-readable text.
+    pub log_json: bool,
+    /// Lines on stdin that shut the player down (compared case-insensitively);
+    /// any other input is ignored.
+    pub quit_keywords: Vec<String>,
+    /// File or M3U playlist started on the first connection, without waiting for
+    /// an order.
+    pub autoplay: Option<String>,
+    pub player: PlayerOptions,
+}
+
+impl Config {
+    /// Builds the configuration from command-line arguments (without the program
+    /// name), falling back to the `--config` file, environment variables and then
+    /// defaults.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+        let args = with_config_file(args.into_iter().collect())?;
+        let mut server_url = None;
+        let mut listen = None;
+        let mut token = None;
+        let mut ping_interval_secs = DEFAULT_PING_INTERVAL_SECS;
+        let mut pong_timeout_secs = DEFAULT_PONG_TIMEOUT_SECS;
+        let mut base_dir = None;
+        let mut state_file = None;
+        let mut track_gains_file = None;
+        let mut max_inline_bytes = DEFAULT_MAX_INLINE_BYTES;
+        let mut background_load_bytes = DEFAULT_BACKGROUND_LOAD_BYTES;
+        let mut history_len = DEFAULT_HISTORY_LEN;
+        let mut volume_ramp_ms = DEFAULT_VOLUME_RAMP.as_millis() as u64;
+        let mut end_fade_ms = 0;
+        let mut max_speed = MAX_SPEED;
+        let mut default_volume = 1.0;
+        let mut target_lufs = DEFAULT_TARGET_LUFS;
+        let mut silent = false;
+        let mut log_json = false;
+        let mut quit_keywords = Vec::new();
+        let mut preferred_devices = Vec::new();
+        let mut resume_finished = ResumeFinished::default();
+        let mut autoplay = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ConfigError::MissingValue(flag.clone()))
+            };
+            match flag.as_str() {
+                "--server" => server_url = Some(value()?),
+                "--listen" => listen = Some(parse_value(&flag, value()?)?),
+                "--token" => token = Some(value()?),
+                "--ping-interval" => ping_interval_secs = parse_value(&flag, value()?)?,
+                "--pong-timeout" => pong_timeout_secs = parse_value(&flag, value()?)?,
+                "--base-dir" => base_dir = Some(value()?),
+                "--state-file" => state_file = Some(expand_tilde(&value()?)),
+                "--track-gains-file" => track_gains_file = Some(expand_tilde(&value()?)),
+                "--max-inline-bytes" => max_inline_bytes = parse_value(&flag, value()?)?,
+                "--background-load-bytes" => background_load_bytes = parse_value(&flag, value()?)?,
+                "--history-len" => history_len = parse_value(&flag, value()?)?,
+                "--volume-ramp-ms" => volume_ramp_ms = parse_value(&flag, value()?)?,
+                "--end-fade-ms" => end_fade_ms = parse_value(&flag, value()?)?,
+                "--max-speed" => {
+                    let value = value()?;
+                    max_speed = parse_value(&flag, value.clone())?;
+                    // Below MIN_SPEED even `speed clamp` would have nothing to pick.
+                    if !(max_speed >= MIN_SPEED && max_speed.is_finite()) {
+                        return Err(ConfigError::InvalidValue { flag, value });
+                    }
+                }
+                "--default-volume" => {
+                    let value = value()?;
+                    default_volume = parse_value(&flag, value.clone())?;
+                    if !(0.0..=MAX_VOLUME).contains(&default_volume) {
+                        return Err(ConfigError::InvalidValue { flag, value });
+                    }
+                }
+                "--autoplay" => autoplay = Some(value()?),
+                "--target-lufs" => {
+                    let value = value()?;
+                    target_lufs = parse_value(&flag, value.clone())?;
+                    if !(-70.0..=0.0).contains(&target_lufs) {
+                        return Err(ConfigError::InvalidValue { flag, value });
+                    }
+                }
+                "--silent" => silent = parse_switch(&flag, inline_value.clone())?,
+                "--log-json" => log_json = parse_switch(&flag, inline_value.clone())?,
+                "--quit-keyword" => quit_keywords.push(value()?.trim().to_lowercase()),
+                "--preferred-device" => preferred_devices.push(value()?),
+                "--resume-finished" => resume_finished = parse_value(&flag, value()?)?,
+                _ => return Err(ConfigError::UnknownArgument(flag)),
+            }
+        }
+
+        let server_url = server_url
+            .or_else(|| env::var(SERVER_ENV).ok())
+            .unwrap_or_else(|| DEFAULT_SERVER_URL.to_string());
+        validate_server_url(&server_url)?;
+
+        let token = token.or_else(|| env::var(TOKEN_ENV).ok());
+        if let Some(token) = &token
+            && HeaderValue::from_str(&format!("Bearer {}", token)).is_err()
+        {
+            return Err(ConfigError::InvalidToken);
+        }
+
+        Ok(Self {
+            server_url,
+            listen,
+            token,
+            ping_interval: (ping_interval_secs > 0)
+                .then(|| Duration::from_secs(ping_interval_secs)),
+            pong_timeout: Duration::from_secs(pong_timeout_secs),
+            silent,
+            log_json,
+            quit_keywords: if quit_keywords.is_empty() {
+                DEFAULT_QUIT_KEYWORDS
+                    .iter()
+                    .map(|k| k.to_string())
+                    .collect()
+            } else {
+                quit_keywords
+            },
+            autoplay,
+            player: PlayerOptions {
+                base_dir: base_dir
+                    .or_else(|| env::var(BASE_DIR_ENV).ok())
+                    .map(|dir| expand_tilde(&dir))
+                    .unwrap_or_default(),
+                state_file,
+                max_inline_bytes,
+                background_load_bytes,
+                history_len,
+                volume_ramp: Duration::from_millis(volume_ramp_ms),
+                max_speed,
+                default_volume,
+                target_lufs,
+                end_fade: Duration::from_millis(end_fade_ms),
+                track_gains_file,
+                preferred_devices,
+                resume_finished,
+            },
+        })
+    }
+}
+
+/// Puts the settings of the file named by `--config`, if any, in front of the
+/// other arguments, so that flags given on the command line win; a switch the
+/// file turns on is turned off again with `--flag=false`. Quit keywords and
+/// preferred devices on the command line replace the file's instead of adding
+/// to them.
+fn with_config_file(args: Vec<String>) -> Result<Vec<String>, ConfigError> {
+    let mut path = None;
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            path = Some(
+                args.next()
+                    .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?,
+            );
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            path = Some(value.to_string());
+        } else {
+            rest.push(arg);
+        }
+    }
+    let Some(path) = path else {
+        return Ok(rest);
+    };
+
+    let mut flags = config_file::read_flags(&expand_tilde(&path)).map_err(|reason| {
+        ConfigError::ConfigFile {
+            path: path.clone(),
+            reason,
+        }
+    })?;
+    for list in ["--quit-keyword", "--preferred-device"] {
+        let is_list_flag = |arg: &String| {
+            arg == list || arg.strip_prefix(list).is_some_and(|v| v.starts_with('='))
+        };
+        if rest.iter().any(is_list_flag) {
+            flags.retain(|flag| !is_list_flag(flag));
+        }
+    }
+    flags.extend(rest);
+    Ok(flags)
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: String) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        flag: flag.to_string(),
+        value,
+    })
+}
+
+/// A switch is on when given bare; `--flag=false` turns off one the config
+/// file turned on.
+fn parse_switch(flag: &str, value: Option<String>) -> Result<bool, ConfigError> {
+    value.map_or(Ok(true), |value| parse_value(flag, value))
+}
+
+fn validate_server_url(url: &str) -> Result<(), ConfigError> {
+    let invalid = |reason: &str| ConfigError::InvalidServerUrl {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    };
+    let uri = url.parse::<Uri>().map_err(|e| invalid(&e.to_string()))?;
+    match uri.scheme_str() {
+        Some("ws") => {}
+        // Checked here rather than on connecting, where it would only be noticed
+        // in the log while the player waits for a connection that never comes.
+        Some("wss") => {
+            return Err(invalid(
+                "wss:// needs TLS support, which this build does not include; use ws:// \
+                 behind a TLS-terminating proxy instead",
+            ));
+        }
+        _ => return Err(invalid("scheme must be ws://")),
+    }
+    if uri.host().is_none() {
+        return Err(invalid("missing host"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::{Mutex, PoisonError};
+
+    /// Held by every test that parses arguments, since parsing reads the
+    /// environment and some tests change it.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        let _env = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    /// Parses `args` with the variables in `vars` set, removing them afterwards.
+    fn parse_with_env(vars: &[(&str, &str)], args: &[&str]) -> Result<Config, ConfigError> {
+        let _env = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        for (name, value) in vars {
+            // SAFETY: every test that reads or changes the environment holds `ENV`,
+            // and nothing else in the tests touches it.
+            unsafe { env::set_var(name, value) };
+        }
+        let config = Config::from_args(args.iter().map(|arg| arg.to_string()));
+        for (name, _) in vars {
+            // SAFETY: as above.
+            unsafe { env::remove_var(name) };
+        }
+        config
+    }
+
+    /// Writes `text` to a config file unique to the test called `name`.
+    fn config_file(name: &str, text: &str) -> PathBuf {
+        let path =
+            env::temp_dir().join(format!("sound_player_{}_{}.toml", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn config_file_settings_apply_and_the_command_line_wins() {
+        let path = config_file("precedence", "max_speed = 2\ndefault_volume = 0.5\n");
+        let path = path.to_str().unwrap();
+        let config = parse(&["--config", path, "--max-speed=3"]).unwrap();
+        assert_eq!(config.player.max_speed, 3.0);
+        assert_eq!(config.player.default_volume, 0.5);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn switches_from_the_config_file_can_be_turned_off() {
+        let path = config_file("switches", "silent = true\nlog_json = true\n");
+        let path = path.to_str().unwrap();
+        let config = parse(&["--config", path]).unwrap();
+        assert!(config.silent && config.log_json);
+
+        let config = parse(&["--config", path, "--silent=false", "--log-json=false"]).unwrap();
+        assert!(!config.silent && !config.log_json);
+        assert!(matches!(
+            parse(&["--silent=maybe"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn list_flags_on_the_command_line_replace_the_config_files() {
+        let path = config_file("lists", "quit_keyword = ['bye', 'halt']\n");
+        let path = path.to_str().unwrap();
+        let config = parse(&["--config", path]).unwrap();
+        assert_eq!(config.quit_keywords, ["bye", "halt"]);
+
+        let config = parse(&["--quit-keyword", "stop", "--config", path]).unwrap();
+        assert_eq!(config.quit_keywords, ["stop"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn config_file_errors_name_the_file_and_line() {
+        let path = config_file("errors", "silent = true\nmax_speed\n");
+        let path = path.to_str().unwrap();
+        let error = parse(&["--config", path]).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!(
+                "Cannot read config file {}: line 2: expected `key = value`",
+                path
+            )
+        );
+        assert!(matches!(
+            parse(&["--config"]),
+            Err(ConfigError::MissingValue(flag)) if flag == "--config"
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn defaults_apply_without_arguments() {
+        let config = parse(&[]).unwrap();
+        assert_eq!(config.server_url, DEFAULT_SERVER_URL);
+        assert_eq!(config.listen, None);
+        assert_eq!(config.token, None);
+        assert_eq!(
+            config.ping_interval,
+            Some(Duration::from_secs(DEFAULT_PING_INTERVAL_SECS))
+        );
+        assert_eq!(config.quit_keywords, DEFAULT_QUIT_KEYWORDS);
+        assert!(!config.silent && !config.log_json);
+        assert_eq!(config.player.base_dir, PathBuf::new());
+        assert_eq!(config.player.default_volume, 1.0);
+    }
+
+    #[test]
+    fn values_follow_a_space_or_an_equals_sign() {
+        let config = parse(&[
+            "--server",
+            "ws://a:1",
+            "--max-speed=2.5",
+            "--history-len",
+            "7",
+        ]);
+        let config = config.unwrap();
+        assert_eq!(config.server_url, "ws://a:1");
+        assert_eq!(config.player.max_speed, 2.5);
+        assert_eq!(config.player.history_len, 7);
+        // Only the first `=` separates the value.
+        let config = parse(&["--token=a=b"]).unwrap();
+        assert_eq!(config.token.as_deref(), Some("a=b"));
+    }
+
+    #[test]
+    fn environment_fills_in_what_the_command_line_leaves_out() {
+        let vars = [
+            (SERVER_ENV, "ws://env:1"),
+            (TOKEN_ENV, "secret"),
+            (BASE_DIR_ENV, "/music"),
+        ];
+        let config = parse_with_env(&vars, &[]).unwrap();
+        assert_eq!(config.server_url, "ws://env:1");
+        assert_eq!(config.token.as_deref(), Some("secret"));
+        assert_eq!(config.player.base_dir, PathBuf::from("/music"));
+
+        let args = ["--server=ws://cli:2", "--token=mine", "--base-dir=/songs"];
+        let config = parse_with_env(&vars, &args).unwrap();
+        assert_eq!(config.server_url, "ws://cli:2");
+        assert_eq!(config.token.as_deref(), Some("mine"));
+        assert_eq!(config.player.base_dir, PathBuf::from("/songs"));
+    }
+
+    #[test]
+    fn unknown_flags_and_missing_values_are_errors() {
+        assert!(matches!(
+            parse(&["--volume", "1"]),
+            Err(ConfigError::UnknownArgument(flag)) if flag == "--volume"
+        ));
+        assert!(matches!(
+            parse(&["--server"]),
+            Err(ConfigError::MissingValue(flag)) if flag == "--server"
+        ));
+        assert!(matches!(
+            parse(&["--history-len=many"]),
+            Err(ConfigError::InvalidValue { flag, value }) if flag == "--history-len" && value == "many"
+        ));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        for args in [
+            ["--max-speed", "0"],
+            ["--max-speed", "inf"],
+            ["--default-volume", "-0.5"],
+            ["--target-lufs", "3"],
+            ["--resume-finished", "sometimes"],
+        ] {
+            assert!(
+                matches!(parse(&args), Err(ConfigError::InvalidValue { .. })),
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn server_urls_must_be_plain_websocket_urls_with_a_host() {
+        for url in ["ws://localhost:9001", "ws://10.0.0.2/player"] {
+            assert!(parse(&["--server", url]).is_ok(), "{}", url);
+        }
+        for (url, reason) in [
+            ("http://host:1", "scheme must be ws://"),
+            ("host:1", "scheme must be ws://"),
+        ] {
+            let error = parse(&["--server", url]).unwrap_err().to_string();
+            assert!(error.ends_with(reason), "{}: {}", url, error);
+        }
+        assert!(matches!(
+            parse(&["--server", "ws:///path"]),
+            Err(ConfigError::InvalidServerUrl { .. })
+        ));
+        let error = parse(&["--server", "wss://host:1"])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("needs TLS support"), "{}", error);
+    }
+
+    #[test]
+    fn tokens_must_fit_in_a_header() {
+        assert!(matches!(
+            parse(&["--token", "line\nbreak"]),
+            Err(ConfigError::InvalidToken)
+        ));
+    }
+
+    #[test]
+    fn ping_interval_zero_disables_the_heartbeat() {
+        let config = parse(&["--ping-interval", "0"]).unwrap();
+        assert_eq!(config.ping_interval, None);
+    }
+
+    #[test]
+    fn quit_keywords_are_normalized() {
+        let config = parse(&["--quit-keyword", " Bye ", "--quit-keyword=STOP"]).unwrap();
+        assert_eq!(config.quit_keywords, ["bye", "stop"]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::fs;
+use std::path::Path;
+
+/// Reads settings from the file at `path`, written in a small subset of TOML:
+/// top-level `key = value` lines with strings, numbers, booleans and
+/// single-line arrays of those, plus `#` comments. Keys are the command-line
+/// flag names without the leading dashes, with `_` or `-` between words.
+///
+/// Each setting is turned into the flag it stands for, so that it goes through
+/// the same parsing and validation as the command line. `true` stands for a
+/// bare switch and `false` for its absence; an array repeats the flag once per
+/// element.
+pub fn read_flags(path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_flags(&text)
+}
+
+fn parse_flags(text: &str) -> Result<Vec<String>, String> {
+    let mut flags = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        parse_line(line, &mut flags).map_err(|reason| format!("line {}: {}", index + 1, reason))?;
+    }
+    Ok(flags)
+}
+
+fn parse_line(line: &str, flags: &mut Vec<String>) -> Result<(), String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+    if line.starts_with('[') {
+        return Err("tables are not supported".to_string());
+    }
+    let (key, value) = line.split_once('=').ok_or("expected `key = value`")?;
+    let key = key.trim();
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!("invalid key '{}'", key));
+    }
+    let flag = format!("--{}", key.replace('_', "-"));
+
+    let mut rest = value.trim_start();
+    let values = if let Some(array) = rest.strip_prefix('[') {
+        rest = array;
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected `,` or `]` in array".to_string());
+            }
+        }
+        values
+    } else {
+        let (value, after) = parse_value(rest)?;
+        rest = after;
+        vec![value]
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected '{}' after the value", rest));
+    }
+
+    for value in values {
+        match value {
+            Value::Bool(true) => flags.push(flag.clone()),
+            Value::Bool(false) => {}
+            Value::Text(text) => flags.push(format!("{}={}", flag, text)),
+        }
+    }
+    Ok(())
+}
+
+enum Value {
+    Bool(bool),
+    /// Strings, and numbers in their written form.
+    Text(String),
+}
+
+/// Parses one value at the start of `input` and returns it with what follows.
+fn parse_value(input: &str) -> Result<(Value, &str), String> {
+    if let Some(body) = input.strip_prefix('"') {
+        let mut text = String::new();
+        let mut chars = body.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::Text(text), &body[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    other => {
+                        return Err(format!("unsupported escape '\\{}'", other.unwrap_or(' ')));
+                    }
+                },
+                c => text.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    if let Some(body) = input.strip_prefix('\'') {
+        // Literal strings take everything up to the closing quote as-is.
+        let end = body.find('\'').ok_or("unterminated string")?;
+        return Ok((Value::Text(body[..end].to_string()), &body[end + 1..]));
+    }
+    let end = input
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+        .unwrap_or(input.len());
+    let (word, rest) = input.split_at(end);
+    match word {
+        "true" => Ok((Value::Bool(true), rest)),
+        "false" => Ok((Value::Bool(false), rest)),
+        "" => Err("missing value".to_string()),
+        number if number.replace('_', "").parse::<f64>().is_ok() => {
+            // TOML allows `_` between digits; the flag parsers don't.
+            Ok((Value::Text(number.replace('_', "")), rest))
+        }
+        other => Err(format!("invalid value '{}' (strings need quotes)", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(text: &str) -> Vec<String> {
+        parse_flags(text).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        parse_flags(text).unwrap_err()
+    }
+
+    #[test]
+    fn strings_become_flags_with_values() {
+        assert_eq!(
+            flags(r#"server = "ws://host:9001""#),
+            ["--server=ws://host:9001"]
+        );
+        assert_eq!(flags("base_dir = '~/music'"), ["--base-dir=~/music"]);
+        assert_eq!(flags("base-dir = '~/music'"), ["--base-dir=~/music"]);
+    }
+
+    #[test]
+    fn basic_strings_take_escapes_and_literal_strings_dont() {
+        assert_eq!(
+            flags(r#"token = "a\"b\\c\nd\te""#),
+            ["--token=a\"b\\c\nd\te"]
+        );
+        assert_eq!(flags(r"token = 'a\nb'"), [r"--token=a\nb"]);
+        assert_eq!(flags("token = \"a # b\""), ["--token=a # b"]);
+    }
+
+    #[test]
+    fn numbers_keep_their_written_form_without_underscores() {
+        assert_eq!(flags("max_speed = 2.5"), ["--max-speed=2.5"]);
+        assert_eq!(flags("target_lufs = -16"), ["--target-lufs=-16"]);
+        assert_eq!(
+            flags("max_inline_bytes = 1_000_000"),
+            ["--max-inline-bytes=1000000"]
+        );
+    }
+
+    #[test]
+    fn booleans_are_switches() {
+        assert_eq!(flags("silent = true"), ["--silent"]);
+        assert!(flags("silent = false").is_empty());
+    }
+
+    #[test]
+    fn arrays_repeat_the_flag() {
+        assert_eq!(
+            flags(r#"quit_keyword = ["bye", 'stop' , "halt",]"#),
+            [
+                "--quit-keyword=bye",
+                "--quit-keyword=stop",
+                "--quit-keyword=halt"
+            ]
+        );
+        assert!(flags("quit_keyword = []").is_empty());
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let text = "# settings\n\n  silent = true  # no output\nmax_speed = 2 #fast\n";
+        assert_eq!(flags(text), ["--silent", "--max-speed=2"]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(
+            error("silent = true\n[player]"),
+            "line 2: tables are not supported"
+        );
+        assert_eq!(error("silent"), "line 1: expected `key = value`");
+        assert_eq!(error("base dir = 'x'"), "line 1: invalid key 'base dir'");
+        assert_eq!(error(" = 'x'"), "line 1: invalid key ''");
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        assert_eq!(
+            error(r#"token = "a\qb""#),
+            r"line 1: unsupported escape '\q'"
+        );
+        assert_eq!(error(r#"token = "abc"#), "line 1: unterminated string");
+        assert_eq!(error("token = 'abc"), "line 1: unterminated string");
+        assert_eq!(error("token ="), "line 1: missing value");
+        assert_eq!(
+            error("token = abc"),
+            "line 1: invalid value 'abc' (strings need quotes)"
+        );
+        assert_eq!(
+            error("max_speed = 2 3"),
+            "line 1: unexpected '3' after the value"
+        );
+    }
+
+    #[test]
+    fn malformed_arrays_are_rejected() {
+        assert_eq!(
+            error("quit_keyword = ['a' 'b']"),
+            "line 1: expected `,` or `]` in array"
+        );
+        assert_eq!(error("quit_keyword = ['a',"), "line 1: missing value");
+    }
+}
@@ -0,0 +1,240 @@
+use std::io::{self, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::{
+    Error, WebSocket, accept_hdr,
+    client::IntoClientRequest,
+    connect,
+    handshake::server::{Callback, ErrorResponse, Request, Response},
+    http::{HeaderValue, StatusCode, header::AUTHORIZATION},
+    stream::MaybeTlsStream,
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Granularity at which a backoff sleep notices a shutdown request.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// Upper bound on how long a read blocks, so the session loop can run timers
+/// and notice shutdown while the connection is idle.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long a client accepted in listen mode has to send its upgrade request.
+/// Long enough for slow links; a client that never finishes doesn't hold up
+/// the listener forever.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause before reading again after a transient error.
+pub const TRANSIENT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+pub type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// Whether the connection is still usable after a read failed with `error`.
+/// Anything else (closed or reset sockets, protocol violations, oversized
+/// messages) leaves it broken, so the session should end and reconnect.
+pub fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+        ),
+        // The offending message is dropped and the stream stays in sync; a full
+        // write buffer drains once the peer reads again.
+        Error::Utf8(_) | Error::WriteBufferFull(_) => true,
+        _ => false,
+    }
+}
+
+/// Tracks outgoing pings and detects a connection whose pongs stopped arriving.
+pub struct Heartbeat {
+    interval: Option<Duration>,
+    timeout: Duration,
+    last_ping: Instant,
+    awaiting_pong_since: Option<Instant>,
+}
+
+impl Heartbeat {
+    pub fn new(interval: Option<Duration>, timeout: Duration) -> Self {
+        Self {
+            interval,
+            timeout,
+            last_ping: Instant::now(),
+            awaiting_pong_since: None,
+        }
+    }
+
+    /// Whether the server failed to answer the outstanding ping within the timeout.
+    pub fn timed_out(&self) -> bool {
+        self.awaiting_pong_since
+            .is_some_and(|since| since.elapsed() > self.timeout)
+    }
+
+    pub fn ping_due(&self) -> bool {
+        self.interval
+            .is_some_and(|interval| self.last_ping.elapsed() >= interval)
+    }
+
+    pub fn ping_sent(&mut self) {
+        self.last_ping = Instant::now();
+        self.awaiting_pong_since.get_or_insert(self.last_ping);
+    }
+
+    pub fn pong_received(&mut self) {
+        self.awaiting_pong_since = None;
+    }
+}
+
+/// Connects to `url`, retrying with exponential backoff until it succeeds or
+/// `running` is cleared. Returns `None` when shutting down or when the request
+/// can't be built from `url` and the token.
+pub fn connect_with_backoff(
+    url: &str,
+    token: Option<&str>,
+    running: &AtomicBool,
+) -> Option<Socket> {
+    let mut request = match url.into_client_request() {
+        Ok(request) => request,
+        Err(e) => {
+            log::error!("Invalid server URL {}: {}", url, e);
+            return None;
+        }
+    };
+    if let Some(token) = token {
+        // The token is validated as a header value at startup.
+        match HeaderValue::from_str(&format!("Bearer {}", token)) {
+            Ok(value) => {
+                request.headers_mut().insert(AUTHORIZATION, value);
+            }
+            Err(e) => {
+                log::error!("Invalid auth token: {}", e);
+                return None;
+            }
+        }
+    }
+
+    let mut backoff = INITIAL_BACKOFF;
+    while running.load(Ordering::SeqCst) {
+        match connect(request.clone()) {
+            Ok((ws, _resp)) => {
+                log::info!("Connected to server at {}", url);
+                if let MaybeTlsStream::Plain(stream) = ws.get_ref()
+                    && let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT))
+                {
+                    log::warn!("Failed to set read timeout: {}", e);
+                }
+                return Some(ws);
+            }
+            Err(Error::Http(response))
+                if matches!(
+                    response.status(),
+                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                ) =>
+            {
+                log::error!(
+                    "Authentication with {} failed: server rejected the upgrade with {}",
+                    url,
+                    response.status()
+                );
+                return None;
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to connect to {}: {} (retrying in {:?})",
+                    url,
+                    e,
+                    backoff
+                );
+                sleep_while_running(backoff, running);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+    None
+}
+
+/// Waits for a client to connect to `listener` and completes its WebSocket
+/// handshake. With a `token`, clients must send it as a bearer token and are
+/// rejected with 401 otherwise. Returns `None` when shutting down or when the
+/// listener fails.
+pub fn accept_client(
+    listener: &TcpListener,
+    token: Option<&str>,
+    running: &AtomicBool,
+) -> Option<Socket> {
+    // Non-blocking, so waiting for a client notices a shutdown request.
+    if let Err(e) = listener.set_nonblocking(true) {
+        log::error!("Failed to configure listener: {}", e);
+        return None;
+    }
+    while running.load(Ordering::SeqCst) {
+        let (stream, peer) = match listener.accept() {
+            Ok(client) => client,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(SHUTDOWN_POLL);
+                continue;
+            }
+            Err(e) => {
+                log::error!("Failed to accept connection: {}", e);
+                return None;
+            }
+        };
+        if let Err(e) = prepare_stream(&stream) {
+            log::warn!("Failed to set up connection from {}: {}", peer, e);
+            continue;
+        }
+        match accept_hdr(MaybeTlsStream::Plain(stream), TokenCheck(token)) {
+            Ok(ws) => {
+                log::info!("Client connected from {}", peer);
+                if let MaybeTlsStream::Plain(stream) = ws.get_ref()
+                    && let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT))
+                {
+                    log::warn!("Failed to set read timeout: {}", e);
+                }
+                return Some(ws);
+            }
+            Err(e) => log::warn!("WebSocket handshake with {} failed: {}", peer, e),
+        }
+    }
+    None
+}
+
+/// Accepted streams inherit non-blocking mode from the listener; the handshake
+/// and the session loop expect blocking reads with a timeout instead. The
+/// short session timeout is only applied once the handshake is done, since the
+/// handshake gives up on the first read that times out.
+fn prepare_stream(stream: &TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+}
+
+/// Handshake callback admitting only clients that present the bearer token, if
+/// one is configured.
+struct TokenCheck<'a>(Option<&'a str>);
+
+impl Callback for TokenCheck<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let Some(token) = self.0 else {
+            return Ok(response);
+        };
+        let expected = format!("Bearer {}", token);
+        let presented = request.headers().get(AUTHORIZATION);
+        if presented.is_some_and(|value| value.as_bytes() == expected.as_bytes()) {
+            return Ok(response);
+        }
+        log::warn!("Rejected client without a valid token");
+        let mut rejection = ErrorResponse::new(Some("Unauthorized".to_string()));
+        *rejection.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(rejection)
+    }
+}
+
+fn sleep_while_running(duration: Duration, running: &AtomicBool) {
+    let mut remaining = duration;
+    while !remaining.is_zero() && running.load(Ordering::SeqCst) {
+        let step = remaining.min(SHUTDOWN_POLL);
+        thread::sleep(step);
+        remaining -= step;
+    }
+}
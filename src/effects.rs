@@ -0,0 +1,553 @@
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Centre frequencies of the equalizer bands. The outer two are shelves, the rest
+/// peaking filters.
+pub const EQ_BANDS_HZ: [f32; 5] = [60.0, 230.0, 910.0, 3600.0, 14000.0];
+
+/// Largest boost or cut, in dB, a single equalizer band accepts.
+pub const MAX_EQ_GAIN_DB: f32 = 12.0;
+
+/// Gain in dB for each of `EQ_BANDS_HZ`; all zeros is flat.
+pub type EqualizerGains = [f32; EQ_BANDS_HZ.len()];
+
+/// Bandwidth of the peaking bands; roughly two octaves.
+const EQ_Q: f32 = 0.7;
+
+/// Effect parameters of one track, adjustable while it plays.
+#[derive(Debug)]
+pub struct EffectControls {
+    pan: AtomicU32,
+    mono: AtomicBool,
+    equalizer: [AtomicU32; EQ_BANDS_HZ.len()],
+    /// Bumped on every equalizer change so the filters know to recompute.
+    equalizer_generation: AtomicU32,
+}
+
+impl EffectControls {
+    pub fn new(pan: f32, mono: bool, equalizer: EqualizerGains) -> Arc<Self> {
+        Arc::new(Self {
+            pan: AtomicU32::new(pan.to_bits()),
+            mono: AtomicBool::new(mono),
+            equalizer: equalizer.map(|gain| AtomicU32::new(gain.to_bits())),
+            equalizer_generation: AtomicU32::new(0),
+        })
+    }
+
+    pub fn pan(&self) -> f32 {
+        f32::from_bits(self.pan.load(Ordering::Relaxed))
+    }
+
+    pub fn set_pan(&self, pan: f32) {
+        self.pan.store(pan.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn mono(&self) -> bool {
+        self.mono.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mono(&self, mono: bool) {
+        self.mono.store(mono, Ordering::Relaxed);
+    }
+
+    pub fn equalizer(&self) -> EqualizerGains {
+        std::array::from_fn(|band| f32::from_bits(self.equalizer[band].load(Ordering::Relaxed)))
+    }
+
+    pub fn set_equalizer(&self, gains: EqualizerGains) {
+        for (band, gain) in self.equalizer.iter().zip(gains) {
+            band.store(gain.to_bits(), Ordering::Relaxed);
+        }
+        self.equalizer_generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn equalizer_generation(&self) -> u32 {
+        self.equalizer_generation.load(Ordering::Acquire)
+    }
+}
+
+/// Plays the average of all channels on every channel while mono is switched on,
+/// so nothing is lost on a single speaker. The channel count stays as it is;
+/// with mono off, or a single channel, samples pass through untouched.
+pub struct Downmix<S> {
+    input: S,
+    controls: Arc<EffectControls>,
+    /// The frame being handed out, read from the input in one go.
+    frame: Vec<Sample>,
+    channel: usize,
+}
+
+impl<S: Source> Downmix<S> {
+    pub fn new(input: S, controls: Arc<EffectControls>) -> Self {
+        Self {
+            input,
+            controls,
+            frame: Vec::new(),
+            channel: 0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Downmix<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.channel < self.frame.len() {
+            self.channel += 1;
+            return Some(self.frame[self.channel - 1]);
+        }
+        let channels = self.input.channels() as usize;
+        if channels < 2 {
+            return self.input.next();
+        }
+        // Whole frames are read even with mono off, so switching it on mid-track
+        // starts on a frame boundary.
+        self.frame.clear();
+        self.frame.extend(self.input.by_ref().take(channels));
+        if self.controls.mono() && !self.frame.is_empty() {
+            let mean = self.frame.iter().sum::<Sample>() / self.frame.len() as Sample;
+            self.frame.fill(mean);
+        }
+        self.channel = 1;
+        self.frame.first().copied()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.frame.len() - self.channel;
+        let (lower, upper) = self.input.size_hint();
+        (
+            lower.saturating_add(buffered),
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+}
+
+impl<S: Source> Source for Downmix<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+        self.frame.clear();
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+/// Shifts a stereo source towards one side by attenuating the other: -1.0 is
+/// full left, 1.0 full right. Sources with a single channel are left alone.
+pub struct Balance<S> {
+    input: S,
+    controls: Arc<EffectControls>,
+    gains: [f32; 2],
+    channel: usize,
+}
+
+impl<S: Source> Balance<S> {
+    pub fn new(input: S, controls: Arc<EffectControls>) -> Self {
+        Self {
+            input,
+            controls,
+            gains: [1.0, 1.0],
+            channel: 0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Balance<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels() as usize;
+        if channels < 2 {
+            return Some(sample);
+        }
+        if self.channel == 0 {
+            let pan = self.controls.pan();
+            self.gains = [1.0 - pan.max(0.0), 1.0 + pan.min(0.0)];
+        }
+        let gain = self.gains.get(self.channel).copied().unwrap_or(1.0);
+        self.channel = (self.channel + 1) % channels;
+        Some(sample * gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Balance<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum BandShape {
+    LowShelf,
+    Peaking,
+    HighShelf,
+}
+
+/// Normalized biquad coefficients (RBJ audio EQ cookbook), `a0` divided out.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    fn new(shape: BandShape, frequency: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            BandShape::Peaking => {
+                let alpha = sin / (2.0 * EQ_Q);
+                (
+                    1.0 + alpha * a,
+                    -2.0 * cos,
+                    1.0 - alpha * a,
+                    1.0 + alpha / a,
+                    -2.0 * cos,
+                    1.0 - alpha / a,
+                )
+            }
+            BandShape::LowShelf | BandShape::HighShelf => {
+                // Shelf slope of 1, the steepest without overshoot.
+                let k = 2.0 * a.sqrt() * sin * FRAC_1_SQRT_2;
+                let sign = match shape {
+                    BandShape::LowShelf => 1.0,
+                    _ => -1.0,
+                };
+                (
+                    a * ((a + 1.0) - sign * (a - 1.0) * cos + k),
+                    sign * 2.0 * a * ((a - 1.0) - sign * (a + 1.0) * cos),
+                    a * ((a + 1.0) - sign * (a - 1.0) * cos - k),
+                    (a + 1.0) + sign * (a - 1.0) * cos + k,
+                    -sign * 2.0 * ((a - 1.0) + sign * (a + 1.0) * cos),
+                    (a + 1.0) + sign * (a - 1.0) * cos - k,
+                )
+            }
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Delay line of one biquad on one channel (direct form I).
+#[derive(Clone, Copy, Default)]
+struct BiquadState {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl BiquadState {
+    fn process(&mut self, filter: &Biquad, x: f32) -> f32 {
+        let y = filter.b0 * x + filter.b1 * self.x1 + filter.b2 * self.x2
+            - filter.a1 * self.y1
+            - filter.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Five-band equalizer over `EQ_BANDS_HZ`. Bands at 0 dB, and bands above the
+/// Nyquist frequency, are skipped entirely, so a flat profile costs nothing.
+pub struct Equalizer<S> {
+    input: S,
+    controls: Arc<EffectControls>,
+    generation: Option<u32>,
+    filters: Vec<Biquad>,
+    /// One delay line per active filter per channel, filter-major.
+    states: Vec<BiquadState>,
+    channel: usize,
+}
+
+impl<S: Source> Equalizer<S> {
+    pub fn new(input: S, controls: Arc<EffectControls>) -> Self {
+        Self {
+            input,
+            controls,
+            generation: None,
+            filters: Vec::new(),
+            states: Vec::new(),
+            channel: 0,
+        }
+    }
+
+    fn update_filters(&mut self) {
+        let generation = self.controls.equalizer_generation();
+        if self.generation == Some(generation) {
+            return;
+        }
+        self.generation = Some(generation);
+
+        let sample_rate = self.input.sample_rate() as f32;
+        let gains = self.controls.equalizer();
+        let last = EQ_BANDS_HZ.len() - 1;
+        self.filters = EQ_BANDS_HZ
+            .iter()
+            .zip(gains)
+            .enumerate()
+            .filter(|&(_, (&frequency, gain))| gain != 0.0 && frequency < sample_rate / 2.0)
+            .map(|(band, (&frequency, gain))| {
+                let shape = match band {
+                    0 => BandShape::LowShelf,
+                    b if b == last => BandShape::HighShelf,
+                    _ => BandShape::Peaking,
+                };
+                Biquad::new(shape, frequency, gain, sample_rate)
+            })
+            .collect();
+        let channels = self.input.channels().max(1) as usize;
+        if self.states.len() != self.filters.len() * channels {
+            self.states = vec![BiquadState::default(); self.filters.len() * channels];
+        }
+    }
+}
+
+impl<S: Source> Iterator for Equalizer<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels().max(1) as usize;
+        if self.channel == 0 {
+            self.update_filters();
+        }
+        let mut y = sample;
+        for (filter, state) in self
+            .filters
+            .iter()
+            .zip(self.states.iter_mut().skip(self.channel).step_by(channels))
+        {
+            y = state.process(filter, y);
+        }
+        self.channel = (self.channel + 1) % channels;
+        Some(y)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Equalizer<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+        self.states.fill(BiquadState::default());
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn balanced(pan: f32, channels: ChannelCount, samples: Vec<Sample>) -> Vec<Sample> {
+        let controls = EffectControls::new(pan, false, EqualizerGains::default());
+        Balance::new(SamplesBuffer::new(channels, 44_100, samples), controls).collect()
+    }
+
+    #[test]
+    fn centred_balance_leaves_stereo_alone() {
+        let samples = vec![0.5, -0.25, 0.75, 1.0];
+        assert_eq!(balanced(0.0, 2, samples.clone()), samples);
+    }
+
+    #[test]
+    fn full_left_silences_the_right_channel() {
+        assert_eq!(
+            balanced(-1.0, 2, vec![0.5, 0.5, -0.5, -0.5]),
+            [0.5, 0.0, -0.5, 0.0]
+        );
+    }
+
+    #[test]
+    fn full_right_silences_the_left_channel() {
+        assert_eq!(
+            balanced(1.0, 2, vec![0.5, 0.5, -0.5, -0.5]),
+            [0.0, 0.5, 0.0, -0.5]
+        );
+    }
+
+    #[test]
+    fn partial_balance_only_attenuates_the_far_side() {
+        assert_eq!(balanced(0.25, 2, vec![1.0, 1.0]), [0.75, 1.0]);
+        assert_eq!(balanced(-0.25, 2, vec![1.0, 1.0]), [1.0, 0.75]);
+    }
+
+    #[test]
+    fn mono_sources_ignore_balance() {
+        assert_eq!(balanced(1.0, 1, vec![0.5, -0.5]), [0.5, -0.5]);
+    }
+
+    #[test]
+    fn balance_changes_apply_from_the_next_frame() {
+        let controls = EffectControls::new(0.0, false, EqualizerGains::default());
+        let source = SamplesBuffer::new(2, 44_100, vec![1.0; 4]);
+        let mut balance = Balance::new(source, controls.clone());
+        assert_eq!(balance.next(), Some(1.0));
+        controls.set_pan(1.0);
+        assert_eq!(balance.next(), Some(1.0));
+        assert_eq!(balance.by_ref().collect::<Vec<_>>(), [0.0, 1.0]);
+    }
+
+    const RATE: SampleRate = 44_100;
+
+    fn equalized(
+        gains: EqualizerGains,
+        sample_rate: SampleRate,
+        samples: Vec<Sample>,
+    ) -> Vec<Sample> {
+        let controls = EffectControls::new(0.0, false, gains);
+        Equalizer::new(SamplesBuffer::new(1, sample_rate, samples), controls).collect()
+    }
+
+    /// Peak level of the last half of `samples`, once the filters have settled.
+    fn settled_peak(samples: &[Sample]) -> f32 {
+        samples[samples.len() / 2..]
+            .iter()
+            .fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    fn sine(frequency: f32, amplitude: f32) -> Vec<Sample> {
+        (0..RATE as usize)
+            .map(|n| amplitude * (2.0 * PI * frequency * n as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn db(gain: f32) -> f32 {
+        20.0 * gain.log10()
+    }
+
+    fn band(index: usize, gain_db: f32) -> EqualizerGains {
+        let mut gains = EqualizerGains::default();
+        gains[index] = gain_db;
+        gains
+    }
+
+    #[test]
+    fn flat_equalizer_passes_samples_through() {
+        let samples = sine(1000.0, 0.5);
+        assert_eq!(
+            equalized(EqualizerGains::default(), RATE, samples.clone()),
+            samples
+        );
+    }
+
+    #[test]
+    fn peaking_band_boosts_its_centre_frequency() {
+        let output = equalized(band(2, MAX_EQ_GAIN_DB), RATE, sine(EQ_BANDS_HZ[2], 0.1));
+        let gain = db(settled_peak(&output) / 0.1);
+        assert!((gain - MAX_EQ_GAIN_DB).abs() < 0.2, "{gain} dB");
+    }
+
+    #[test]
+    fn peaking_band_leaves_distant_frequencies_alone() {
+        let output = equalized(band(2, MAX_EQ_GAIN_DB), RATE, sine(12_000.0, 0.1));
+        let gain = db(settled_peak(&output) / 0.1);
+        assert!(gain.abs() < 1.0, "{gain} dB");
+    }
+
+    #[test]
+    fn low_shelf_cuts_everything_below_it() {
+        let output = equalized(band(0, -6.0), RATE, vec![0.5; RATE as usize]);
+        let gain = db(output.last().unwrap() / 0.5);
+        assert!((gain + 6.0).abs() < 0.01, "{gain} dB");
+    }
+
+    #[test]
+    fn high_shelf_leaves_dc_alone() {
+        let output = equalized(band(4, MAX_EQ_GAIN_DB), RATE, vec![0.5; RATE as usize]);
+        assert!((output.last().unwrap() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn bands_above_nyquist_are_skipped() {
+        let samples = vec![0.5, -0.5, 0.25, -0.25];
+        assert_eq!(
+            equalized(band(4, MAX_EQ_GAIN_DB), 16_000, samples.clone()),
+            samples
+        );
+    }
+
+    #[test]
+    fn equalizer_changes_take_effect_mid_track() {
+        let controls = EffectControls::new(0.0, false, EqualizerGains::default());
+        let source = SamplesBuffer::new(1, RATE, vec![0.5; RATE as usize * 2]);
+        let mut equalizer = Equalizer::new(source, controls.clone());
+        assert_eq!(equalizer.by_ref().take(100).last(), Some(0.5));
+        controls.set_equalizer(band(0, -6.0));
+        let gain = db(equalizer.last().unwrap() / 0.5);
+        assert!((gain + 6.0).abs() < 0.01, "{gain} dB");
+    }
+}
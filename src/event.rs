@@ -0,0 +1,52 @@
+use crate::meter::Levels;
+use crate::sound_player::PlayerSnapshot;
+use serde::Serialize;
+
+/// Unsolicited notifications pushed to the client, independent of any order.
+/// Events of players other than the default one carry that player's id.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PlayerEvent {
+    /// The track played to its end on its own (not stopped or replaced).
+    TrackFinished {
+        song: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        player_id: Option<String>,
+    },
+    /// A large track given to `play` is being opened in the background; `ready`
+    /// or `load_failed` follows.
+    Loading {
+        song: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        player_id: Option<String>,
+    },
+    /// A track opened in the background has started playing.
+    Ready {
+        song: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        player_id: Option<String>,
+    },
+    /// A track being opened in the background turned out not to play.
+    LoadFailed {
+        song: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        player_id: Option<String>,
+        error_kind: &'static str,
+        message: String,
+    },
+    /// Player state after an order changed it; sent only while subscribed.
+    StateChanged {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        player_id: Option<String>,
+        #[serde(flatten)]
+        snapshot: PlayerSnapshot,
+    },
+    /// Peak and RMS per channel of the current track; sent periodically while
+    /// subscribed to levels.
+    Levels {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        player_id: Option<String>,
+        #[serde(flatten)]
+        levels: Levels,
+    },
+}
@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tungstenite::http::Uri;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a stalled stream may go without delivering data.
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_REDIRECTS: usize = 5;
+/// Bytes kept behind the read position so the decoder can seek back a little
+/// while probing. Older data is dropped, so continuous streams use bounded memory.
+const REWIND_WINDOW: usize = 1 << 20;
+
+/// Whether `name` refers to a URL rather than a file.
+pub fn is_url(name: &str) -> bool {
+    name.starts_with("http://") || name.starts_with("https://")
+}
+
+/// A response body being downloaded as it's read.
+pub struct HttpStream {
+    body: Box<dyn Read + Send + Sync>,
+    /// Value of the Content-Type header, without parameters.
+    pub content_type: Option<String>,
+    history: VecDeque<u8>,
+    /// Absolute offset in the body of the next byte returned by `read`.
+    position: u64,
+    /// Absolute offset just past the newest downloaded byte.
+    downloaded: u64,
+}
+
+impl HttpStream {
+    /// Issues a GET for `url`, following redirects, and returns once the response
+    /// headers are in. Only plain `http://` is available in this build.
+    pub fn open(url: &str) -> Result<Self, String> {
+        let mut url = url.to_string();
+        for _ in 0..=MAX_REDIRECTS {
+            match request(&url)? {
+                Response::Body { body, content_type } => {
+                    return Ok(Self {
+                        body,
+                        content_type,
+                        history: VecDeque::new(),
+                        position: 0,
+                        downloaded: 0,
+                    });
+                }
+                Response::Redirect(location) => url = absolute_location(&url, &location)?,
+            }
+        }
+        Err(format!("more than {} redirects", MAX_REDIRECTS))
+    }
+
+    fn history_start(&self) -> u64 {
+        self.downloaded - self.history.len() as u64
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.downloaded {
+            // Replay bytes the decoder seeked back over.
+            let offset = (self.position - self.history_start()) as usize;
+            let (front, back) = self.history.as_slices();
+            let replay = if offset < front.len() {
+                &front[offset..]
+            } else {
+                &back[offset - front.len()..]
+            };
+            let n = replay.len().min(buf.len());
+            buf[..n].copy_from_slice(&replay[..n]);
+            self.position += n as u64;
+            return Ok(n);
+        }
+
+        let n = self.body.read(buf)?;
+        self.history.extend(&buf[..n]);
+        let excess = self.history.len().saturating_sub(REWIND_WINDOW);
+        self.history.drain(..excess);
+        self.downloaded += n as u64;
+        self.position = self.downloaded;
+        Ok(n)
+    }
+}
+
+impl Seek for HttpStream {
+    /// Supports seeking back within the rewind window and forward by reading
+    /// ahead; the end of the stream is unknown.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(_) => None,
+        };
+        let target = target
+            .filter(|&target| target >= self.history_start())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "cannot seek this far in a stream",
+                )
+            })?;
+
+        if target <= self.downloaded {
+            self.position = target;
+        } else {
+            self.position = self.downloaded;
+            let skip = target - self.downloaded;
+            io::copy(&mut self.by_ref().take(skip), &mut io::sink())?;
+        }
+        Ok(self.position)
+    }
+}
+
+enum Response {
+    Body {
+        body: Box<dyn Read + Send + Sync>,
+        content_type: Option<String>,
+    },
+    Redirect(String),
+}
+
+fn request(url: &str) -> Result<Response, String> {
+    let uri = url.parse::<Uri>().map_err(|e| e.to_string())?;
+    match uri.scheme_str() {
+        Some("http") => {}
+        Some("https") => {
+            return Err(
+                "https:// requires TLS support, which this build does not include".to_string(),
+            );
+        }
+        _ => return Err("unsupported URL scheme".to_string()),
+    }
+    let host = uri.host().ok_or("missing host")?;
+    let port = uri.port_u16().unwrap_or(80);
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?
+        .next()
+        .ok_or_else(|| format!("cannot resolve {}", host))?;
+    let mut stream =
+        TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: sound_player/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        path,
+        uri.authority().map_or(host, |a| a.as_str()),
+        env!("CARGO_PKG_VERSION")
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader
+        .read_line(&mut status_line)
+        .map_err(|e| e.to_string())?;
+    // Shoutcast servers answer with "ICY 200 OK" instead of an HTTP status line.
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| format!("malformed response: {}", status_line.trim()))?;
+
+    let mut content_type = None;
+    let mut location = None;
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed while reading headers".to_string());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-type" => {
+                content_type = value.split(';').next().map(|v| v.trim().to_string());
+            }
+            "location" => location = Some(value.to_string()),
+            "content-length" => content_length = value.parse::<u64>().ok(),
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+    }
+
+    match status {
+        200..=299 => {}
+        301 | 302 | 303 | 307 | 308 => {
+            return location
+                .map(Response::Redirect)
+                .ok_or_else(|| format!("redirect {} without a location", status));
+        }
+        _ => return Err(format!("server responded with status {}", status)),
+    }
+
+    let body: Box<dyn Read + Send + Sync> = if chunked {
+        Box::new(ChunkedReader {
+            inner: reader,
+            remaining: 0,
+            done: false,
+        })
+    } else if let Some(length) = content_length {
+        Box::new(reader.take(length))
+    } else {
+        Box::new(reader)
+    };
+    Ok(Response::Body { body, content_type })
+}
+
+/// Resolves a redirect target, which may be relative to the URL that produced it.
+fn absolute_location(url: &str, location: &str) -> Result<String, String> {
+    if is_url(location) {
+        return Ok(location.to_string());
+    }
+    let uri = url.parse::<Uri>().map_err(|e| e.to_string())?;
+    let origin = format!(
+        "{}://{}",
+        uri.scheme_str().unwrap_or("http"),
+        uri.authority().map_or("", |a| a.as_str())
+    );
+    if location.starts_with('/') {
+        Ok(format!("{}{}", origin, location))
+    } else {
+        let dir = uri.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+        Ok(format!("{}{}/{}", origin, dir, location))
+    }
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body.
+struct ChunkedReader<R> {
+    inner: R,
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            let mut line = String::new();
+            self.inner.read_line(&mut line)?;
+            if line.trim().is_empty() {
+                // CRLF terminating the previous chunk.
+                line.clear();
+                self.inner.read_line(&mut line)?;
+            }
+            let size = line.trim().split(';').next().unwrap_or_default();
+            self.remaining = u64::from_str_radix(size, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad chunk size"))?;
+            if self.remaining == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+        let limit = buf.len().min(self.remaining as usize);
+        let n = self.inner.read(&mut buf[..limit])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,24 @@
+pub mod backend;
+pub mod command;
+pub mod config;
+pub mod config_file;
+pub mod connection;
+pub mod effects;
+pub mod event;
+pub mod http_stream;
+pub mod loudness;
+pub mod metadata;
+pub mod meter;
+pub mod metrics;
+pub mod mock_backend;
+pub mod null_backend;
+pub mod order;
+pub mod playlist;
+pub mod recording;
+pub mod response;
+pub mod shutdown;
+pub mod sniff;
+pub mod sound_player;
+pub mod sound_player_manager;
+pub mod stretch;
+pub mod track_gains;
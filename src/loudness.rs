@@ -0,0 +1,263 @@
+use rodio::Source;
+use std::f64::consts::PI;
+
+/// Loudness tracks are normalized to unless configured otherwise, the
+/// ReplayGain 2.0 reference level.
+pub const DEFAULT_TARGET_LUFS: f32 = -18.0;
+
+/// Most a quiet track is boosted by, so near-silent recordings aren't blown up
+/// to full scale.
+pub const MAX_NORMALIZE_BOOST_DB: f32 = 12.0;
+
+/// Length of the measurement sub-blocks; gating blocks are four of them
+/// (400 ms), overlapping by three.
+const SUB_BLOCKS_PER_SECOND: u32 = 10;
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+
+/// Blocks quieter than this are ignored outright (EBU R128 absolute gate).
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Blocks this far below the loudness of the ungated blocks are ignored too.
+const RELATIVE_GATE_LU: f64 = 10.0;
+
+/// Biquad with `a0` divided out, in double precision since the mean squares
+/// summed afterwards span hours of audio.
+#[derive(Clone, Copy)]
+struct Filter {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Filter {
+    /// The two stages of the K-weighting of ITU-R BS.1770, derived for
+    /// `sample_rate` the way libebur128 does: a high shelf modelling the head,
+    /// then a high-pass (the "RLB" curve).
+    fn k_weighting(sample_rate: f64) -> [Filter; 2] {
+        let k = (PI * 1681.974450955533 / sample_rate).tan();
+        let (q, vh) = (0.7071752369554196, 10f64.powf(3.999843853973347 / 20.0));
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Filter {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        };
+
+        let k = (PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Filter {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        };
+        [shelf, high_pass]
+    }
+}
+
+/// Delay line of one filter on one channel (direct form I).
+#[derive(Clone, Copy, Default)]
+struct FilterState {
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl FilterState {
+    fn process(&mut self, filter: &Filter, x: f64) -> f64 {
+        let y = filter.b[0] * x + filter.b[1] * self.x[0] + filter.b[2] * self.x[1]
+            - filter.a[0] * self.y[0]
+            - filter.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// Integrated loudness of a whole track per EBU R128, in LUFS.
+///
+/// Samples are fed interleaved. Every channel is weighted equally, which is
+/// what the standard prescribes for mono and stereo; surround layouts come out
+/// slightly off.
+pub struct LoudnessMeter {
+    filters: [Filter; 2],
+    /// Two delay lines per channel.
+    states: Vec<[FilterState; 2]>,
+    channel: usize,
+    sub_block_frames: usize,
+    frames: usize,
+    sum: f64,
+    /// Mean square of each finished sub-block, summed over channels.
+    sub_blocks: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            filters: Filter::k_weighting(f64::from(sample_rate.max(1))),
+            states: vec![Default::default(); channels.max(1) as usize],
+            channel: 0,
+            sub_block_frames: (sample_rate / SUB_BLOCKS_PER_SECOND).max(1) as usize,
+            frames: 0,
+            sum: 0.0,
+            sub_blocks: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        let [first, second] = &mut self.states[self.channel];
+        let weighted = second.process(
+            &self.filters[1],
+            first.process(&self.filters[0], f64::from(sample)),
+        );
+        self.sum += weighted * weighted;
+        self.channel += 1;
+        if self.channel == self.states.len() {
+            self.channel = 0;
+            self.frames += 1;
+            if self.frames == self.sub_block_frames {
+                self.sub_blocks.push(self.sum / self.frames as f64);
+                self.frames = 0;
+                self.sum = 0.0;
+            }
+        }
+    }
+
+    /// Loudness of everything pushed so far, or `None` if it's shorter than one
+    /// gating block or entirely below the absolute gate.
+    pub fn integrated(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self
+            .sub_blocks
+            .windows(SUB_BLOCKS_PER_BLOCK)
+            .map(|window| window.iter().sum::<f64>() / SUB_BLOCKS_PER_BLOCK as f64)
+            .filter(|&power| loudness(power) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+        let threshold = loudness(mean(&blocks)) - RELATIVE_GATE_LU;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&power| loudness(power) > threshold)
+            .collect();
+        Some(loudness(mean(&gated)))
+    }
+}
+
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Runs `source` to its end through a `LoudnessMeter`.
+pub fn measure(source: impl Source) -> Option<f64> {
+    let mut meter = LoudnessMeter::new(source.channels(), source.sample_rate());
+    for sample in source {
+        meter.push(sample);
+    }
+    meter.integrated()
+}
+
+/// Linear gain that brings a track measured at `loudness` LUFS to `target`,
+/// boosting by at most `MAX_NORMALIZE_BOOST_DB`.
+pub fn normalization_gain(loudness: f64, target: f32) -> f32 {
+    let db = (f64::from(target) - loudness).min(f64::from(MAX_NORMALIZE_BOOST_DB));
+    10f64.powf(db / 20.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    /// Feeds `seconds` of a 1 kHz sine peaking at `dbfs` to both channels.
+    fn push_sine(meter: &mut LoudnessMeter, dbfs: f64, seconds: u32) {
+        let amplitude = 10f64.powf(dbfs / 20.0);
+        for n in 0..RATE * seconds {
+            let sample =
+                (amplitude * (2.0 * PI * 1000.0 * f64::from(n) / f64::from(RATE)).sin()) as f32;
+            meter.push(sample);
+            meter.push(sample);
+        }
+    }
+
+    fn assert_lufs(measured: Option<f64>, expected: f64) {
+        let measured = measured.expect("no loudness measured");
+        assert!((measured - expected).abs() < 0.1, "{measured} LUFS");
+    }
+
+    #[test]
+    fn stereo_sine_reads_at_its_reference_level() {
+        // EBU Tech 3341, case 1: a 1 kHz sine at -23 dBFS measures -23 LUFS.
+        let mut meter = LoudnessMeter::new(2, RATE);
+        push_sine(&mut meter, -23.0, 20);
+        assert_lufs(meter.integrated(), -23.0);
+    }
+
+    #[test]
+    fn loudness_follows_level() {
+        let mut meter = LoudnessMeter::new(2, RATE);
+        push_sine(&mut meter, -33.0, 20);
+        assert_lufs(meter.integrated(), -33.0);
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let mut meter = LoudnessMeter::new(2, RATE);
+        push_sine(&mut meter, -23.0, 10);
+        for _ in 0..RATE * 2 * 10 {
+            meter.push(0.0);
+        }
+        assert_lufs(meter.integrated(), -23.0);
+    }
+
+    #[test]
+    fn quiet_passages_are_gated_out_relative_to_the_rest() {
+        // EBU Tech 3341, case 3: -36 dBFS is above the absolute gate but more
+        // than 10 LU below the loud part, so it doesn't count.
+        let mut meter = LoudnessMeter::new(2, RATE);
+        push_sine(&mut meter, -36.0, 10);
+        push_sine(&mut meter, -23.0, 60);
+        push_sine(&mut meter, -36.0, 10);
+        assert_lufs(meter.integrated(), -23.0);
+    }
+
+    #[test]
+    fn nothing_is_measured_without_a_full_gating_block() {
+        let mut meter = LoudnessMeter::new(2, RATE);
+        assert_eq!(meter.integrated(), None);
+        for _ in 0..RATE * 2 * 3 / 10 {
+            meter.push(0.5);
+        }
+        assert_eq!(meter.integrated(), None);
+    }
+
+    #[test]
+    fn silence_alone_has_no_loudness() {
+        let mut meter = LoudnessMeter::new(1, RATE);
+        for _ in 0..RATE * 5 {
+            meter.push(0.0);
+        }
+        assert_eq!(meter.integrated(), None);
+    }
+
+    #[test]
+    fn normalization_gain_moves_towards_the_target() {
+        assert!((normalization_gain(-28.0, -18.0) - 10f32.powf(0.5)).abs() < 1e-4);
+        assert!((normalization_gain(-8.0, -18.0) - 10f32.powf(-0.5)).abs() < 1e-4);
+        assert_eq!(normalization_gain(-18.0, -18.0), 1.0);
+    }
+
+    #[test]
+    fn normalization_boost_is_capped() {
+        let cap = 10f32.powf(MAX_NORMALIZE_BOOST_DB / 20.0);
+        assert!((normalization_gain(-60.0, -18.0) - cap).abs() < 1e-4);
+        // Cuts aren't limited.
+        assert!(normalization_gain(10.0, -18.0) < 0.05);
+    }
+}
@@ -1,44 +1,178 @@
-mod command;
-mod order;
-mod sound_player;
-mod sound_player_manager;
 use env_logger::Env;
+use log::LevelFilter;
+use sound_player::backend::AudioBackend;
+use sound_player::config::{self, Config};
+use sound_player::connection::{self, Heartbeat, Socket};
+use sound_player::null_backend::NullBackend;
+use sound_player::shutdown;
+use sound_player::sound_player_manager::SoundPlayerManager;
+use std::io::{ErrorKind, Write};
+use std::net::TcpListener;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
-use tungstenite::{Message, connect};
+use tungstenite::{Bytes, Error, Message};
 
 fn main() {
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n{}", e, config::USAGE);
+            std::process::exit(2);
+        }
+    };
+
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
 
-    env_logger::Builder::from_env(
-        Env::default().default_filter_or("sound_player_manager=debug,sound_player=debug"),
-    )
-    .init();
+    // Without RUST_LOG everything of ours passes the filter and the max level
+    // keeps it at debug, so `setloglevel` can go up to trace as well as down.
+    let filter_from_env = std::env::var_os(env_logger::DEFAULT_FILTER_ENV).is_some();
+    let mut logger =
+        env_logger::Builder::from_env(Env::default().default_filter_or("sound_player=trace"));
+    if config.log_json {
+        logger.format(|buf, record| {
+            let line = serde_json::json!({
+                "timestamp": buf.timestamp_millis().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    logger.init();
+    if !filter_from_env {
+        log::set_max_level(LevelFilter::Debug);
+    }
 
-    let mut manager = sound_player_manager::SoundPlayerManager::new().unwrap();
+    if let Err(e) = shutdown::install_handlers(running.clone()) {
+        log::warn!("Could not install signal handlers: {}", e);
+    }
 
+    let quit_keywords = config.quit_keywords.clone();
     std::thread::spawn(move || {
-        let mut buf = String::new();
-        let _ = std::io::stdin().read_line(&mut buf);
-        r.store(false, Ordering::SeqCst);
+        // Stdin may carry other input; only a quit keyword shuts down, and running
+        // out of input (e.g. stdin is /dev/null) doesn't.
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else { break };
+            if quit_keywords.contains(&line.trim().to_lowercase()) {
+                log::info!("Quit requested on stdin");
+                r.store(false, Ordering::SeqCst);
+                break;
+            }
+        }
     });
 
-    let (mut ws, _resp) = connect("ws://127.0.0.1:9001").unwrap();
-    log::info!("Connected to server");
+    log::info!("Starting at volume {}", config.player.default_volume);
+    if config.silent {
+        log::info!("Running without audio output");
+        let manager = SoundPlayerManager::with_backend(NullBackend::new(), config.player.clone());
+        serve(&config, manager, &running);
+    } else {
+        let manager = SoundPlayerManager::new(config.player.clone()).unwrap();
+        serve(&config, manager, &running);
+    }
+
+    println!("Shutdown complete");
+}
+
+/// Keeps (re)connecting to the server, or accepting clients in listen mode, and
+/// serving orders until shutdown.
+fn serve<B: AudioBackend>(
+    config: &Config,
+    mut manager: SoundPlayerManager<B>,
+    running: &AtomicBool,
+) {
+    let listener = match config.listen.map(TcpListener::bind).transpose() {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Cannot listen on {:?}: {}", config.listen, e);
+            shutdown(manager, None);
+            return;
+        }
+    };
+    if let Some(addr) = config.listen {
+        log::info!("Listening for controllers on {}", addr);
+    }
+    let token = config.token.as_deref();
+    let next_socket = || match &listener {
+        Some(listener) => connection::accept_client(listener, token, running),
+        None => connection::connect_with_backoff(&config.server_url, token, running),
+    };
+
+    let mut autoplay = config.autoplay.as_deref();
+    while let Some(mut ws) = next_socket() {
+        if let Some(target) = autoplay.take() {
+            manager.autoplay(target);
+        }
+        let mut heartbeat = Heartbeat::new(config.ping_interval, config.pong_timeout);
+        run_session(&mut ws, &mut manager, &mut heartbeat, running);
+
+        if !running.load(Ordering::SeqCst) {
+            shutdown(manager, Some(&mut ws));
+            return;
+        }
+        close(&mut ws);
+        match listener {
+            Some(_) => log::info!("Client disconnected, waiting for the next one"),
+            None => log::info!("Connection lost, reconnecting"),
+        }
+    }
+    shutdown(manager, None);
+}
+
+/// Runs on every way out of `serve`: closes the connection, if there is one,
+/// then drops the manager, which saves the session and stops all playback.
+fn shutdown<B: AudioBackend>(manager: SoundPlayerManager<B>, ws: Option<&mut Socket>) {
+    if let Some(ws) = ws {
+        close(ws);
+    }
+    drop(manager);
+}
+
+/// Attempts the close handshake; the socket may well be broken already, so a
+/// failure is only logged.
+fn close(ws: &mut Socket) {
+    if let Err(e) = ws.close(None) {
+        log::debug!("Error while closing connection: {}", e);
+    }
+}
 
+/// Serves orders on `ws` until the connection drops or shutdown is requested.
+fn run_session<B: AudioBackend>(
+    ws: &mut Socket,
+    manager: &mut SoundPlayerManager<B>,
+    heartbeat: &mut Heartbeat,
+    running: &AtomicBool,
+) {
     while running.load(Ordering::SeqCst) {
+        if heartbeat.timed_out() {
+            log::warn!("No pong received in time, treating connection as dead");
+            break;
+        }
+        if heartbeat.ping_due() {
+            if let Err(e) = ws.send(Message::Ping(Bytes::new())) {
+                log::error!("Failed to send ping: {}", e);
+                break;
+            }
+            heartbeat.ping_sent();
+        }
+        for event in manager.pending_events() {
+            let json = serde_json::to_string(&event).unwrap();
+            if let Err(e) = ws.send(Message::Text(json.into())) {
+                log::error!("Failed to send event: {}", e);
+                return;
+            }
+        }
+
         match ws.read() {
             Ok(msg) => match msg {
                 Message::Text(txt) => {
-                    let order: crate::order::Order = serde_json::from_str(&txt).unwrap();
-                    log::info!("Received order: {:?}", order);
+                    let reply = manager.process_message(&txt);
 
-                    let response = manager.process_order(order);
-
-                    let json = serde_json::to_string(&response).unwrap();
+                    let json = serde_json::to_string(&reply).unwrap();
 
                     if let Err(e) = ws.send(tungstenite::protocol::Message::Text(
                         tungstenite::Utf8Bytes::from(json),
@@ -47,19 +181,104 @@ fn main() {
                         break;
                     }
                 }
+                Message::Ping(_) => {
+                    // tungstenite queues the matching pong on read; flush it right away.
+                    if let Err(e) = ws.flush() {
+                        log::error!("Failed to send pong: {}", e);
+                        break;
+                    }
+                }
+                Message::Pong(_) => heartbeat.pong_received(),
                 Message::Close(_) => {
                     log::info!("Server closed connection");
                     break;
                 }
                 _ => {}
             },
+            // Read timeouts, and signals interrupting the read (shutdown is checked
+            // at the top of the loop).
+            Err(Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) => {}
+            Err(Error::ConnectionClosed | Error::AlreadyClosed) => {
+                log::warn!("WebSocket connection closed");
+                break;
+            }
+            Err(e) if connection::is_transient(&e) => {
+                log::warn!("Transient WebSocket error, retrying: {}", e);
+                std::thread::sleep(connection::TRANSIENT_RETRY_DELAY);
+            }
             Err(e) => {
-                log::error!("WebSocket error: {}", e);
-                std::thread::sleep(std::time::Duration::from_millis(100));
+                log::error!("WebSocket error, dropping the connection: {}", e);
+                break;
             }
         }
     }
+}
 
-    ws.close(None).unwrap();
-    println!("Shutdown complete");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use sound_player::sound_player::PlayerOptions;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Sends an order and waits for its response, skipping any events.
+    fn request(ws: &mut Socket, command_name: &str, parameters: &[&str]) -> Value {
+        let order = json!({ "command_name": command_name, "parameters": parameters });
+        ws.send(Message::Text(order.to_string().into())).unwrap();
+        loop {
+            if let Message::Text(txt) = ws.read().unwrap() {
+                let reply: Value = serde_json::from_str(&txt).unwrap();
+                if reply.get("command").is_some() {
+                    return reply;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn playback_survives_a_dropped_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let running = AtomicBool::new(true);
+        let options = PlayerOptions {
+            base_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("songs"),
+            ..PlayerOptions::default()
+        };
+        let mut manager = SoundPlayerManager::with_backend(NullBackend::new(), options);
+
+        let client = thread::spawn(move || {
+            let (mut ws, _) = tungstenite::connect(&url).unwrap();
+            let played = request(&mut ws, "play", &["cone.mp3"]);
+            // Gone without a close handshake, as when the network drops.
+            drop(ws);
+            thread::sleep(Duration::from_millis(300));
+
+            let (mut ws, _) = tungstenite::connect(&url).unwrap();
+            let status = request(&mut ws, "status", &[]);
+            let _ = ws.close(None);
+            (played, status)
+        });
+        for _ in 0..2 {
+            let mut ws = connection::accept_client(&listener, None, &running).unwrap();
+            let mut heartbeat = Heartbeat::new(None, Duration::from_secs(5));
+            run_session(&mut ws, &mut manager, &mut heartbeat, &running);
+        }
+        let (played, status) = client.join().unwrap();
+
+        assert_eq!(played["status"], "ok", "{}", played);
+        let snapshot = &status["data"];
+        assert_eq!(snapshot["current_song"], "cone.mp3", "{}", status);
+        assert_eq!(snapshot["is_playing"], true, "{}", status);
+        assert!(
+            snapshot["position_secs"].as_f64().unwrap() > 0.2,
+            "{}",
+            status
+        );
+    }
 }
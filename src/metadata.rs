@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use symphonia::core::{
+    errors::Error,
+    formats::FormatOptions,
+    io::{MediaSource, MediaSourceStream},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::{Hint, ProbeResult},
+};
+
+/// Descriptive tags of an audio file. Missing tags are `None`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<String>,
+}
+
+impl TrackMetadata {
+    fn apply(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                Some(StandardTagKey::TrackNumber) => &mut self.track_number,
+                _ => continue,
+            };
+            field.get_or_insert_with(|| tag.value.to_string());
+        }
+    }
+}
+
+fn probe(path: &Path) -> Result<ProbeResult, Error> {
+    let file = File::open(path)?;
+    probe_source(
+        Box::new(file),
+        path.extension().and_then(|ext| ext.to_str()),
+    )
+}
+
+fn probe_source(source: Box<dyn MediaSource>, ext: Option<&str>) -> Result<ProbeResult, Error> {
+    let stream = MediaSourceStream::new(source, Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = ext {
+        hint.with_extension(ext);
+    }
+
+    symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )
+}
+
+/// Reads title/artist/album/track number from `path`, looking at both tags found
+/// ahead of the container (e.g. ID3v2) and tags stored inside it.
+pub fn read_metadata(path: &Path) -> Result<TrackMetadata, Error> {
+    Ok(metadata_of(probe(path)?))
+}
+
+/// Like `read_metadata`, for audio of the given format held in memory.
+pub fn read_inline_metadata(data: &Arc<[u8]>, format: &str) -> Result<TrackMetadata, Error> {
+    Ok(metadata_of(probe_source(
+        Box::new(Cursor::new(data.clone())),
+        Some(format),
+    )?))
+}
+
+fn metadata_of(mut probed: ProbeResult) -> TrackMetadata {
+    let mut metadata = TrackMetadata::default();
+    if let Some(revision) = probed.format.metadata().current() {
+        metadata.apply(revision);
+    }
+    if let Some(probed_metadata) = probed.metadata.get()
+        && let Some(revision) = probed_metadata.current()
+    {
+        metadata.apply(revision);
+    }
+    metadata
+}
+
+/// Sample rate and channel count of decoded audio.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// What the container says about its default track. Fields it doesn't report
+/// are `None`.
+#[derive(Debug, Clone, Default)]
+pub struct StreamInfo {
+    pub duration: Option<Duration>,
+    pub format: Option<AudioFormat>,
+    /// Short codec name, e.g. "mp3" or "pcm_s16le".
+    pub codec: Option<String>,
+}
+
+/// Reads the length, format and codec of the default track in `path` from the
+/// container headers, without decoding any audio.
+pub fn read_stream_info(path: &Path) -> Result<StreamInfo, Error> {
+    Ok(stream_info_of(probe(path)?))
+}
+
+/// Like `read_stream_info`, for audio of the given format held in memory.
+pub fn read_inline_stream_info(data: &Arc<[u8]>, format: &str) -> Result<StreamInfo, Error> {
+    Ok(stream_info_of(probe_source(
+        Box::new(Cursor::new(data.clone())),
+        Some(format),
+    )?))
+}
+
+fn stream_info_of(probed: ProbeResult) -> StreamInfo {
+    let Some(track) = probed.format.default_track() else {
+        return StreamInfo::default();
+    };
+    let params = &track.codec_params;
+    let duration = params
+        .time_base
+        .zip(params.n_frames)
+        .map(|(time_base, frames)| time_base.calc_time(frames))
+        .map(|time| Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac));
+    let format = params
+        .sample_rate
+        .zip(params.channels)
+        .map(|(sample_rate, channels)| AudioFormat {
+            sample_rate,
+            channels: channels.count() as u16,
+        });
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|descriptor| descriptor.short_name.to_string());
+    StreamInfo {
+        duration,
+        format,
+        codec,
+    }
+}
@@ -0,0 +1,195 @@
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// How often level events are sent when the subscriber doesn't say.
+pub const DEFAULT_LEVELS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shortest interval between level events a subscriber may ask for.
+pub const MIN_LEVELS_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Frames a `LevelMeter` sums up locally before merging them into the shared
+/// window, so the audio thread rarely touches the lock.
+const PUBLISH_FRAMES: usize = 512;
+
+/// Peak and RMS amplitude of each channel over some stretch of audio, on a
+/// linear scale where 1.0 is full scale.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Levels {
+    pub peak: Vec<f32>,
+    pub rms: Vec<f32>,
+}
+
+impl Levels {
+    /// The levels with every value multiplied by `gain`.
+    pub fn scaled(mut self, gain: f32) -> Self {
+        for level in self.peak.iter_mut().chain(self.rms.iter_mut()) {
+            *level *= gain;
+        }
+        self
+    }
+}
+
+/// Running sums for one or more channels.
+#[derive(Debug, Default)]
+struct Window {
+    peak: Vec<f32>,
+    sum_squares: Vec<f64>,
+    frames: usize,
+}
+
+impl Window {
+    fn reset(&mut self, channels: usize) {
+        self.peak.clear();
+        self.peak.resize(channels, 0.0);
+        self.sum_squares.clear();
+        self.sum_squares.resize(channels, 0.0);
+        self.frames = 0;
+    }
+
+    /// Adds `other` in; a channel count change starts the window over.
+    fn merge(&mut self, other: &Window) {
+        if self.peak.len() != other.peak.len() {
+            self.reset(other.peak.len());
+        }
+        for (peak, other) in self.peak.iter_mut().zip(&other.peak) {
+            *peak = peak.max(*other);
+        }
+        for (sum, other) in self.sum_squares.iter_mut().zip(&other.sum_squares) {
+            *sum += other;
+        }
+        self.frames += other.frames;
+    }
+}
+
+/// Switches metering of one track on and off and collects what it measured.
+#[derive(Debug, Default)]
+pub struct MeterControl {
+    enabled: AtomicBool,
+    window: Mutex<Window>,
+}
+
+impl MeterControl {
+    pub fn new(enabled: bool) -> Arc<Self> {
+        Arc::new(Self {
+            enabled: AtomicBool::new(enabled),
+            window: Mutex::default(),
+        })
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Levels of everything played since the last call, or `None` if nothing was.
+    pub fn take(&self) -> Option<Levels> {
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        if window.frames == 0 {
+            return None;
+        }
+        let frames = window.frames as f64;
+        let levels = Levels {
+            peak: window.peak.clone(),
+            rms: window
+                .sum_squares
+                .iter()
+                .map(|sum| (sum / frames).sqrt() as f32)
+                .collect(),
+        };
+        let channels = window.peak.len();
+        window.reset(channels);
+        Some(levels)
+    }
+}
+
+/// Passes `input` through unchanged, measuring the peak and RMS of each channel
+/// while metering is enabled on its `MeterControl`. Costs a load per frame when
+/// disabled.
+pub struct LevelMeter<S> {
+    input: S,
+    control: Arc<MeterControl>,
+    pending: Window,
+    channel: usize,
+    enabled: bool,
+}
+
+impl<S: Source> LevelMeter<S> {
+    pub fn new(input: S, control: Arc<MeterControl>) -> Self {
+        Self {
+            input,
+            control,
+            pending: Window::default(),
+            channel: 0,
+            enabled: false,
+        }
+    }
+
+    /// Hands the pending sums over, unless the reader holds the lock right now;
+    /// they're then kept for the next attempt rather than blocking playback.
+    fn publish(&mut self) {
+        if let Ok(mut window) = self.control.window.try_lock() {
+            window.merge(&self.pending);
+            let channels = self.pending.peak.len();
+            self.pending.reset(channels);
+        }
+    }
+}
+
+impl<S: Source> Iterator for LevelMeter<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels().max(1) as usize;
+        if self.channel == 0 {
+            self.enabled = self.control.enabled.load(Ordering::Relaxed);
+            if self.enabled && self.pending.peak.len() != channels {
+                self.pending.reset(channels);
+            }
+        }
+        if self.enabled {
+            let channel = self.channel.min(channels - 1);
+            self.pending.peak[channel] = self.pending.peak[channel].max(sample.abs());
+            self.pending.sum_squares[channel] += f64::from(sample * sample);
+            if self.channel + 1 == channels {
+                self.pending.frames += 1;
+                if self.pending.frames >= PUBLISH_FRAMES {
+                    self.publish();
+                }
+            }
+        }
+        self.channel = (self.channel + 1) % channels;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for LevelMeter<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+        self.channel = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Name under which orders for unknown commands are counted, so junk input can't
+/// grow the table without bound.
+const UNKNOWN_COMMAND: &str = "<unknown>";
+
+/// In-process counters of the orders handled since startup.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Metrics {
+    pub orders: u64,
+    pub errors: u64,
+    /// Orders per canonical command name.
+    pub by_command: BTreeMap<&'static str, u64>,
+    /// Failed orders per error kind.
+    pub by_error: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    /// Counts one order for `command` (`None` if it didn't name a known command)
+    /// and, if it failed, its error kind.
+    pub fn record(&mut self, command: Option<&'static str>, error_kind: Option<&'static str>) {
+        self.orders += 1;
+        *self
+            .by_command
+            .entry(command.unwrap_or(UNKNOWN_COMMAND))
+            .or_default() += 1;
+        if let Some(kind) = error_kind {
+            self.errors += 1;
+            *self.by_error.entry(kind).or_default() += 1;
+        }
+    }
+}
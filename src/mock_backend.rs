@@ -0,0 +1,212 @@
+use crate::backend::{AudioBackend, AudioSink, LoadOptions, LoadedTrack, TrackSource};
+use crate::effects::EqualizerGains;
+use crate::meter::Levels;
+use crate::recording::CaptureSender;
+use crate::sound_player::{SoundPlayerError, SoundPlayerResult};
+use rodio::source::SeekError;
+use std::sync::{
+    Arc, Mutex, PoisonError,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+/// A call made against `MockBackend` or one of its sinks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockCall {
+    Load(TrackSource),
+    Play,
+    Pause,
+    Stop,
+    SetVolume(f32),
+    SetSpeed(f32),
+    SetTempo(f32),
+    SetPan(f32),
+    SetMono(bool),
+    SetEqualizer(EqualizerGains),
+    Seek(Duration),
+    SetDevice(String),
+    Reopen,
+    /// `true` when recording starts, `false` when it's switched off.
+    Record(bool),
+    SetMetering(bool),
+}
+
+type CallLog = Arc<Mutex<Vec<MockCall>>>;
+
+fn record(calls: &CallLog, call: MockCall) {
+    calls
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(call);
+}
+
+/// Backend that produces no sound and records every call it receives, so order
+/// handling can be exercised without an audio device. Clones share the same log.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    calls: CallLog,
+    /// Set by `lose_stream`, cleared by `reopen`.
+    stream_lost: Arc<AtomicBool>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every call recorded so far, in order.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Behaves as if the output device went away: loading fails, as it does
+    /// with rodio, until the stream is reopened.
+    pub fn lose_stream(&self) {
+        self.stream_lost.store(true, Ordering::SeqCst);
+    }
+}
+
+impl AudioBackend for MockBackend {
+    type Sink = MockSink;
+    type Prepared = TrackSource;
+
+    fn prepare(source: &TrackSource) -> SoundPlayerResult<TrackSource> {
+        Ok(source.clone())
+    }
+
+    /// Recorded as `MockCall::Load`, whether or not the track was prepared ahead.
+    fn start(
+        &self,
+        source: TrackSource,
+        options: LoadOptions,
+    ) -> SoundPlayerResult<LoadedTrack<MockSink>> {
+        if self.stream_lost.load(Ordering::SeqCst) {
+            return Err(SoundPlayerError::InvalidStreamHandle);
+        }
+        record(&self.calls, MockCall::Load(source));
+        Ok(LoadedTrack {
+            sink: MockSink {
+                calls: self.calls.clone(),
+                paused: AtomicBool::new(options.paused),
+                stopped: AtomicBool::new(false),
+                volume: Mutex::new(options.volume),
+                position: Mutex::new(Duration::ZERO),
+            },
+            duration: None,
+            format: None,
+        })
+    }
+
+    fn list_devices(&self) -> SoundPlayerResult<Vec<String>> {
+        Ok(vec!["mock".to_string()])
+    }
+
+    fn set_device(&mut self, name: &str) -> SoundPlayerResult<()> {
+        record(&self.calls, MockCall::SetDevice(name.to_string()));
+        Ok(())
+    }
+
+    fn stream_healthy(&self) -> bool {
+        !self.stream_lost.load(Ordering::SeqCst)
+    }
+
+    fn reopen(&mut self) -> SoundPlayerResult<()> {
+        record(&self.calls, MockCall::Reopen);
+        self.stream_lost.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// The fork records into the same log.
+    fn fork(&self) -> SoundPlayerResult<Self> {
+        Ok(self.clone())
+    }
+}
+
+/// Sink handed out by `MockBackend`. Keeps just enough state for the player's
+/// queries to answer consistently; its position only moves when seeked.
+pub struct MockSink {
+    calls: CallLog,
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    volume: Mutex<f32>,
+    position: Mutex<Duration>,
+}
+
+impl AudioSink for MockSink {
+    fn play(&self) {
+        record(&self.calls, MockCall::Play);
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    fn pause(&self) {
+        record(&self.calls, MockCall::Pause);
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    fn stop(&self) {
+        record(&self.calls, MockCall::Stop);
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    fn empty(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    fn volume(&self) -> f32 {
+        *self.volume.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set_volume(&self, volume: f32) {
+        record(&self.calls, MockCall::SetVolume(volume));
+        *self.volume.lock().unwrap_or_else(PoisonError::into_inner) = volume;
+    }
+
+    fn set_speed(&self, speed: f32) {
+        record(&self.calls, MockCall::SetSpeed(speed));
+    }
+
+    fn set_tempo(&self, tempo: f32) {
+        record(&self.calls, MockCall::SetTempo(tempo));
+    }
+
+    fn set_pan(&self, pan: f32) {
+        record(&self.calls, MockCall::SetPan(pan));
+    }
+
+    fn set_mono(&self, mono: bool) {
+        record(&self.calls, MockCall::SetMono(mono));
+    }
+
+    fn set_equalizer(&self, gains: EqualizerGains) {
+        record(&self.calls, MockCall::SetEqualizer(gains));
+    }
+
+    fn try_seek(&self, position: Duration) -> Result<(), SeekError> {
+        record(&self.calls, MockCall::Seek(position));
+        *self.position.lock().unwrap_or_else(PoisonError::into_inner) = position;
+        Ok(())
+    }
+
+    fn get_pos(&self) -> Duration {
+        *self.position.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record(&self, sender: Option<CaptureSender>) {
+        record(&self.calls, MockCall::Record(sender.is_some()));
+    }
+
+    fn set_metering(&self, enabled: bool) {
+        record(&self.calls, MockCall::SetMetering(enabled));
+    }
+
+    fn take_levels(&self) -> Option<Levels> {
+        None
+    }
+}
@@ -0,0 +1,213 @@
+use crate::backend::{
+    AudioBackend, AudioSink, LoadOptions, LoadedTrack, TrackSource, check_reversible,
+    open_audio_file,
+};
+use crate::effects::EqualizerGains;
+use crate::metadata::{StreamInfo, read_inline_stream_info, read_stream_info};
+use crate::meter::Levels;
+use crate::recording::CaptureSender;
+use crate::sound_player::SoundPlayerResult;
+use log::debug;
+use rodio::source::SeekError;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Backend for machines without an audio device. Tracks are never decoded or
+/// output; their position advances with the wall clock (scaled by speed and
+/// tempo) so the player behaves as if it were playing them.
+#[derive(Default)]
+pub struct NullBackend;
+
+impl NullBackend {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl AudioBackend for NullBackend {
+    type Sink = NullSink;
+    /// Length and format of the track, as far as they are known.
+    type Prepared = StreamInfo;
+
+    /// Checks that files can be opened and reads their length and format from the
+    /// container headers. URLs are not fetched; they, and files whose length can't
+    /// be determined, play until stopped.
+    fn prepare(source: &TrackSource) -> SoundPlayerResult<StreamInfo> {
+        Ok(match source {
+            TrackSource::File(path) => {
+                open_audio_file(path)?;
+                read_stream_info(path).unwrap_or_else(|e| {
+                    debug!("Could not read stream info of {}: {}", path.display(), e);
+                    StreamInfo::default()
+                })
+            }
+            TrackSource::Url(_) => StreamInfo::default(),
+            TrackSource::Reversed(path) => {
+                open_audio_file(path)?;
+                let info = read_stream_info(path).unwrap_or_default();
+                check_reversible(path, info.duration)?;
+                info
+            }
+            TrackSource::Inline { data, format } => read_inline_stream_info(data, format)
+                .unwrap_or_else(|e| {
+                    debug!("Could not read stream info of inline audio: {}", e);
+                    StreamInfo::default()
+                }),
+        })
+    }
+
+    fn start(
+        &self,
+        info: StreamInfo,
+        options: LoadOptions,
+    ) -> SoundPlayerResult<LoadedTrack<NullSink>> {
+        let duration = info.duration;
+        Ok(LoadedTrack {
+            sink: NullSink {
+                state: Mutex::new(NullState {
+                    volume: options.volume,
+                    speed: options.speed,
+                    tempo: options.tempo,
+                    stopped: false,
+                    elapsed: Duration::ZERO,
+                    playing_since: (!options.paused).then(Instant::now),
+                }),
+                duration,
+            },
+            duration,
+            format: info.format,
+        })
+    }
+
+    fn list_devices(&self) -> SoundPlayerResult<Vec<String>> {
+        Ok(vec!["null".to_string()])
+    }
+
+    fn set_device(&mut self, _name: &str) -> SoundPlayerResult<()> {
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> SoundPlayerResult<()> {
+        Ok(())
+    }
+
+    fn fork(&self) -> SoundPlayerResult<Self> {
+        Ok(Self)
+    }
+}
+
+struct NullState {
+    volume: f32,
+    speed: f32,
+    tempo: f32,
+    stopped: bool,
+    /// Position at the last pause, seek, speed or tempo change.
+    elapsed: Duration,
+    /// When playback last (re)started; `None` while paused.
+    playing_since: Option<Instant>,
+}
+
+impl NullState {
+    fn position(&self) -> Duration {
+        let running = self
+            .playing_since
+            .map(|since| since.elapsed().mul_f32(self.speed * self.tempo))
+            .unwrap_or_default();
+        self.elapsed + running
+    }
+
+    /// Folds time played so far into `elapsed` and restarts the clock.
+    fn rebase(&mut self) {
+        self.elapsed = self.position();
+        if self.playing_since.is_some() {
+            self.playing_since = Some(Instant::now());
+        }
+    }
+}
+
+pub struct NullSink {
+    state: Mutex<NullState>,
+    duration: Option<Duration>,
+}
+
+impl NullSink {
+    fn state(&self) -> MutexGuard<'_, NullState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl AudioSink for NullSink {
+    fn play(&self) {
+        let mut state = self.state();
+        state.playing_since.get_or_insert_with(Instant::now);
+    }
+
+    fn pause(&self) {
+        let mut state = self.state();
+        state.elapsed = state.position();
+        state.playing_since = None;
+    }
+
+    fn stop(&self) {
+        self.state().stopped = true;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state().playing_since.is_none()
+    }
+
+    fn empty(&self) -> bool {
+        let state = self.state();
+        state.stopped || self.duration.is_some_and(|d| state.position() >= d)
+    }
+
+    fn volume(&self) -> f32 {
+        self.state().volume
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.state().volume = volume;
+    }
+
+    fn set_speed(&self, speed: f32) {
+        let mut state = self.state();
+        state.rebase();
+        state.speed = speed;
+    }
+
+    fn set_tempo(&self, tempo: f32) {
+        let mut state = self.state();
+        state.rebase();
+        state.tempo = tempo;
+    }
+
+    fn set_pan(&self, _pan: f32) {}
+
+    fn set_mono(&self, _mono: bool) {}
+
+    fn set_equalizer(&self, _gains: EqualizerGains) {}
+
+    fn try_seek(&self, position: Duration) -> Result<(), SeekError> {
+        let mut state = self.state();
+        state.rebase();
+        state.elapsed = position;
+        Ok(())
+    }
+
+    fn get_pos(&self) -> Duration {
+        let position = self.state().position();
+        match self.duration {
+            Some(duration) => position.min(duration),
+            None => position,
+        }
+    }
+
+    /// There is no audio to copy, so recordings stay empty.
+    fn record(&self, _sender: Option<CaptureSender>) {}
+
+    fn set_metering(&self, _enabled: bool) {}
+
+    fn take_levels(&self) -> Option<Levels> {
+        None
+    }
+}
@@ -1,7 +1,31 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Order {
     pub command_name: String,
     pub parameters: Vec<String>,
+    /// Opaque client-chosen identifier, echoed back in the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Player the order is addressed to; the default player when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_id: Option<String>,
+}
+
+/// Parameters longer than this are elided in log lines and response messages.
+const MAX_DISPLAYED_PARAMETER_LEN: usize = 256;
+
+impl Order {
+    /// The parameters joined for display, with bulky ones (such as inline audio)
+    /// replaced by their length.
+    pub fn display_parameters(&self) -> String {
+        self.parameters
+            .iter()
+            .map(|param| match param.len() {
+                len if len > MAX_DISPLAYED_PARAMETER_LEN => format!("<{} bytes>", len),
+                _ => param.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 }
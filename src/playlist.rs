@@ -0,0 +1,142 @@
+use crate::http_stream::is_url;
+use crate::sound_player::check_extension;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Entries of an M3U or M3U8 playlist, in order. Comment and directive lines
+/// (starting with `#`) and blank lines are skipped. Relative paths are resolved
+/// against `dir`, the directory the playlist lives in; URLs are kept as-is.
+pub fn parse_m3u(text: &str, dir: &Path) -> Vec<String> {
+    text.trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|entry| {
+            if is_url(entry) {
+                entry.to_string()
+            } else {
+                dir.join(entry).display().to_string()
+            }
+        })
+        .collect()
+}
+
+/// Whether `name` names an M3U or M3U8 playlist, going by its extension.
+pub fn is_playlist(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+}
+
+/// Whether a file name contains `*` or `?` wildcards.
+pub fn is_wildcard(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
+/// Matches `name` against a pattern where `*` stands for any run of characters
+/// and `?` for exactly one.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name position it was tried against.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Audio files in `dir` whose names match `pattern` (all of them without one),
+/// sorted by path. Only files with a supported extension are included, and
+/// subdirectories are not descended into.
+pub fn list_tracks(dir: &Path, pattern: Option<&str>) -> io::Result<Vec<String>> {
+    let mut tracks: Vec<String> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| !kind.is_dir()))
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            check_extension(&name).is_ok()
+                && pattern.is_none_or(|pattern| wildcard_match(pattern, &name))
+        })
+        .map(|entry| entry.path().display().to_string())
+        .collect();
+    tracks.sort();
+    Ok(tracks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_dir(dir: &Path, entries: &[&str]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| dir.join(entry).display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn m3u_entries_are_resolved_against_the_playlist() {
+        let dir = Path::new("music");
+        let text = "one.mp3\nalbum/two.flac\n";
+        assert_eq!(
+            parse_m3u(text, dir),
+            in_dir(dir, &["one.mp3", "album/two.flac"])
+        );
+    }
+
+    #[test]
+    fn m3u_comments_directives_and_blank_lines_are_skipped() {
+        let dir = Path::new("music");
+        let text = "\u{feff}#EXTM3U\n\n#EXTINF:123,Artist - One\none.mp3\r\n  \n# a comment\n  two.mp3  \n";
+        assert_eq!(parse_m3u(text, dir), in_dir(dir, &["one.mp3", "two.mp3"]));
+    }
+
+    #[test]
+    fn m3u_urls_and_absolute_paths_are_kept() {
+        let absolute = std::env::temp_dir().join("three.mp3").display().to_string();
+        let text = format!("http://example.com/one.mp3\nhttps://example.com/two.ogg\n{absolute}\n");
+        assert_eq!(
+            parse_m3u(&text, Path::new("music")),
+            [
+                "http://example.com/one.mp3".to_string(),
+                "https://example.com/two.ogg".to_string(),
+                absolute,
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_m3u_has_no_entries() {
+        assert!(parse_m3u("#EXTM3U\n", Path::new("music")).is_empty());
+    }
+
+    #[test]
+    fn playlists_are_recognised_by_extension() {
+        assert!(is_playlist("mix.m3u"));
+        assert!(is_playlist("dir/Mix.M3U8"));
+        assert!(!is_playlist("song.mp3"));
+        assert!(!is_playlist("m3u"));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::metadata::AudioFormat;
+use log::{debug, error};
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+/// Samples collected on the audio thread before they're handed to the writer.
+const CHUNK: usize = 4096;
+
+/// Size of a canonical 16-bit PCM WAV header.
+const HEADER_LEN: u32 = 44;
+
+/// How long the writer waits for audio before checking whether the recording was
+/// stopped, e.g. while playback is paused.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// Where a `Capture` sends the samples it taps off.
+pub type CaptureSender = Sender<Vec<Sample>>;
+
+/// A WAV file being written by `record_to_wav`.
+#[derive(Debug)]
+pub struct Recording {
+    path: PathBuf,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Recording {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Finalizes the file with whatever has been received so far.
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Creates `path` and starts a thread that writes everything sent to the returned
+/// sender into it as 16-bit PCM WAV in `format`. The file is finalized, header
+/// sizes included, when the recording is stopped or every sender has been dropped.
+///
+/// Recording costs a copy of each sample on the audio thread; conversion and disk
+/// writes happen on the writer thread. The file grows by
+/// `sample_rate * channels * 2` bytes per second, about 10 MB a minute for CD
+/// audio.
+pub fn record_to_wav(path: &Path, format: AudioFormat) -> io::Result<(Recording, CaptureSender)> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_header(&mut file, format, 0)?;
+    let (sender, chunks) = mpsc::channel();
+    let recording = Recording {
+        path: path.to_path_buf(),
+        stopped: Arc::new(AtomicBool::new(false)),
+    };
+    let path = recording.path.clone();
+    let stopped = recording.stopped.clone();
+    thread::spawn(
+        move || match write_samples(file, format, chunks, &stopped) {
+            Ok(bytes) => debug!("Finished recording {} ({} bytes)", path.display(), bytes),
+            Err(e) => error!("Recording to {} failed: {}", path.display(), e),
+        },
+    );
+    Ok((recording, sender))
+}
+
+fn write_samples(
+    mut file: BufWriter<File>,
+    format: AudioFormat,
+    chunks: Receiver<Vec<Sample>>,
+    stopped: &AtomicBool,
+) -> io::Result<u32> {
+    // WAV sizes are 32-bit; past 4 GiB the header is left saturated.
+    let mut data_len: u32 = 0;
+    loop {
+        // After a stop, keep taking what the capture still flushes until it goes
+        // quiet.
+        let chunk = match chunks.recv_timeout(STOP_POLL) {
+            Ok(chunk) => chunk,
+            Err(RecvTimeoutError::Timeout) if stopped.load(Ordering::SeqCst) => break,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        for sample in &chunk {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            file.write_all(&value.to_le_bytes())?;
+        }
+        data_len = data_len.saturating_add(chunk.len() as u32 * 2);
+    }
+    file.seek(SeekFrom::Start(0))?;
+    write_header(&mut file, format, data_len)?;
+    file.flush()?;
+    Ok(data_len)
+}
+
+fn write_header(out: &mut impl Write, format: AudioFormat, data_len: u32) -> io::Result<()> {
+    let channels = format.channels.max(1);
+    let block_align = channels * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(HEADER_LEN - 8).saturating_add(data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&format.sample_rate.to_le_bytes())?;
+    out.write_all(&(format.sample_rate * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())
+}
+
+/// Switches the recording of one track on and off from outside the audio thread.
+#[derive(Debug, Default)]
+pub struct CaptureControl {
+    changed: AtomicBool,
+    sender: Mutex<Option<CaptureSender>>,
+}
+
+impl CaptureControl {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Starts sending the track's samples to `sender`, or stops with `None`.
+    pub fn set(&self, sender: Option<CaptureSender>) {
+        *self.sender.lock().unwrap_or_else(PoisonError::into_inner) = sender;
+        self.changed.store(true, Ordering::Release);
+    }
+}
+
+/// Passes `input` through unchanged, copying its samples to a recording while one
+/// is set on the `CaptureControl`. The recording ends with the input.
+pub struct Capture<S> {
+    input: S,
+    control: Arc<CaptureControl>,
+    sender: Option<CaptureSender>,
+    buffer: Vec<Sample>,
+}
+
+impl<S: Source> Capture<S> {
+    pub fn new(input: S, control: Arc<CaptureControl>) -> Self {
+        Self {
+            input,
+            control,
+            sender: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(sender) = &self.sender
+            && !self.buffer.is_empty()
+            && sender.send(std::mem::take(&mut self.buffer)).is_err()
+        {
+            // The writer gave up; stop collecting.
+            self.sender = None;
+        }
+        self.buffer.clear();
+    }
+}
+
+impl<S: Source> Iterator for Capture<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.control.changed.swap(false, Ordering::Acquire) {
+            self.flush();
+            self.sender = self
+                .control
+                .sender
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+        }
+        let Some(sample) = self.input.next() else {
+            self.flush();
+            self.sender = None;
+            return None;
+        };
+        if self.sender.is_some() {
+            self.buffer.push(sample);
+            if self.buffer.len() >= CHUNK {
+                self.flush();
+            }
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Capture<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)
+    }
+}
+
+impl<S> Drop for Capture<S> {
+    /// Hands over what's left when the track is stopped before its end.
+    fn drop(&mut self) {
+        if let Some(sender) = &self.sender
+            && !self.buffer.is_empty()
+        {
+            let _ = sender.send(std::mem::take(&mut self.buffer));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::sound_player::SoundPlayerError;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseStatus {
+    Ok,
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The `player_id` of the order, echoed back.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub player_id: Option<String>,
+    pub status: ResponseStatus,
+    pub command: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl CommandResponse {
+    pub fn ok(command: &str, message: String) -> Self {
+        Self {
+            id: None,
+            player_id: None,
+            status: ResponseStatus::Ok,
+            command: command.to_string(),
+            message,
+            error_kind: None,
+            data: None,
+        }
+    }
+
+    pub fn error(command: &str, error_kind: &'static str, message: String) -> Self {
+        Self {
+            id: None,
+            player_id: None,
+            status: ResponseStatus::Error,
+            command: command.to_string(),
+            message,
+            error_kind: Some(error_kind),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Option<Value>) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn with_id(mut self, id: Option<String>) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    pub fn with_player_id(mut self, player_id: Option<String>) -> Self {
+        self.player_id = player_id;
+        self
+    }
+}
+
+/// An error response for a failed command, with the error's kind and message.
+/// The command name is left empty; set it with `with_command`.
+impl From<SoundPlayerError> for CommandResponse {
+    fn from(error: SoundPlayerError) -> Self {
+        CommandResponse::error("", error.kind(), error.message())
+    }
+}
+
+/// What gets sent back for one incoming message: a single response, or one per
+/// order when the client sent a batch.
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Reply {
+    Single(CommandResponse),
+    Batch(Vec<CommandResponse>),
+}
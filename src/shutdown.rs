@@ -0,0 +1,45 @@
+use log::info;
+use std::io;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+/// How often the signal flag is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Raised from the signal handler; only async-signal-safe work may happen there,
+/// so a thread picks it up and forwards it.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+    // Restore the default action so a second signal kills a stuck shutdown.
+    // SAFETY: signal(2) is async-signal-safe, and SIG_DFL is a valid action
+    // for the signal being handled.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// Clears `running` when the process receives SIGINT or SIGTERM.
+pub fn install_handlers(running: Arc<AtomicBool>) -> io::Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // SAFETY: `on_signal` is an `extern "C"` fn with the signature signal(2)
+        // expects, and it only stores into an atomic and resets the action,
+        // both of which are async-signal-safe.
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    std::thread::spawn(move || {
+        while !SIGNALLED.load(Ordering::SeqCst) {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        info!("Received termination signal, shutting down");
+        running.store(false, Ordering::SeqCst);
+    });
+    Ok(())
+}
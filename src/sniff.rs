@@ -0,0 +1,199 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Bytes read from the start of a file to recognise it; enough for the first
+/// Ogg page header, segment table included, and the packet signature after it.
+const HEADER_LEN: usize = 512;
+
+/// Audio format recognised from the first bytes of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniffedFormat {
+    Wav,
+    Flac,
+    Mp3,
+    Mp4,
+    OggVorbis,
+    OggOpus,
+    OggFlac,
+    OggSpeex,
+    Aiff,
+    Caf,
+    Matroska,
+}
+
+impl SniffedFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            SniffedFormat::Wav => "WAV",
+            SniffedFormat::Flac => "FLAC",
+            SniffedFormat::Mp3 => "MP3",
+            SniffedFormat::Mp4 => "MP4/AAC",
+            SniffedFormat::OggVorbis => "Ogg Vorbis",
+            SniffedFormat::OggOpus => "Ogg Opus",
+            SniffedFormat::OggFlac => "Ogg FLAC",
+            SniffedFormat::OggSpeex => "Ogg Speex",
+            SniffedFormat::Aiff => "AIFF",
+            SniffedFormat::Caf => "CAF",
+            SniffedFormat::Matroska => "Matroska/WebM",
+        }
+    }
+
+    /// Whether this build has a decoder for the format, going by the rodio
+    /// features enabled in Cargo.toml. Symphonia has no Opus or Speex decoder
+    /// at all.
+    pub fn decodable(self) -> bool {
+        matches!(
+            self,
+            SniffedFormat::Wav
+                | SniffedFormat::Flac
+                | SniffedFormat::Mp3
+                | SniffedFormat::Mp4
+                | SniffedFormat::OggVorbis
+        )
+    }
+}
+
+/// Recognises the format from the first bytes of a file, or `None` if they
+/// match no known signature.
+pub fn sniff(header: &[u8]) -> Option<SniffedFormat> {
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+    if at(0, b"RIFF") && at(8, b"WAVE") {
+        Some(SniffedFormat::Wav)
+    } else if at(0, b"fLaC") {
+        Some(SniffedFormat::Flac)
+    } else if at(0, b"OggS") {
+        sniff_ogg(header)
+    } else if at(0, b"FORM") && (at(8, b"AIFF") || at(8, b"AIFC")) {
+        Some(SniffedFormat::Aiff)
+    } else if at(0, b"caff") {
+        Some(SniffedFormat::Caf)
+    } else if at(0, &[0x1a, 0x45, 0xdf, 0xa3]) {
+        Some(SniffedFormat::Matroska)
+    } else if at(4, b"ftyp") {
+        Some(SniffedFormat::Mp4)
+    } else if at(0, b"ID3") {
+        Some(SniffedFormat::Mp3)
+    } else {
+        // An MPEG audio frame sync with a layer set; layer 0 would be ADTS AAC.
+        match header {
+            [0xff, second, ..] if second & 0xe0 == 0xe0 && second & 0x06 != 0 => {
+                Some(SniffedFormat::Mp3)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Tells the codecs in an Ogg stream apart by the signature of the first
+/// packet, which follows the page header and its segment table.
+fn sniff_ogg(header: &[u8]) -> Option<SniffedFormat> {
+    let segments = *header.get(26)? as usize;
+    let packet = header.get(27 + segments..)?;
+    if packet.starts_with(b"OpusHead") {
+        Some(SniffedFormat::OggOpus)
+    } else if packet.starts_with(b"\x01vorbis") {
+        Some(SniffedFormat::OggVorbis)
+    } else if packet.starts_with(b"\x7fFLAC") {
+        Some(SniffedFormat::OggFlac)
+    } else if packet.starts_with(b"Speex   ") {
+        Some(SniffedFormat::OggSpeex)
+    } else {
+        None
+    }
+}
+
+/// Sniffs the format of `reader` from its start and rewinds it afterwards.
+pub fn sniff_reader<R: Read + Seek>(reader: &mut R) -> io::Result<Option<SniffedFormat>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    reader
+        .by_ref()
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(sniff(&header))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    /// First Ogg page header with one segment, followed by `packet`.
+    fn ogg(packet: &[u8]) -> Vec<u8> {
+        let mut header = b"OggS".to_vec();
+        header.resize(26, 0);
+        header.extend([1, packet.len() as u8]);
+        header.extend(packet);
+        header
+    }
+
+    #[test]
+    fn containers_are_recognised_by_their_magic() {
+        let cases: [(&[u8], SniffedFormat); 8] = [
+            (b"RIFF\0\0\0\0WAVEfmt ", SniffedFormat::Wav),
+            (b"fLaC\0\0\0\x22", SniffedFormat::Flac),
+            (b"FORM\0\0\0\0AIFFCOMM", SniffedFormat::Aiff),
+            (b"FORM\0\0\0\0AIFCFVER", SniffedFormat::Aiff),
+            (b"caff\0\x01\0\0", SniffedFormat::Caf),
+            (b"\x1a\x45\xdf\xa3\x9f\x42\x86", SniffedFormat::Matroska),
+            (b"\0\0\0\x20ftypM4A ", SniffedFormat::Mp4),
+            (b"ID3\x04\0\0\0\0\0\0", SniffedFormat::Mp3),
+        ];
+        for (header, format) in cases {
+            assert_eq!(sniff(header), Some(format), "{header:?}");
+        }
+    }
+
+    #[test]
+    fn bare_mpeg_frames_are_mp3_but_adts_is_not() {
+        assert_eq!(sniff(&[0xff, 0xfb, 0x90, 0x64]), Some(SniffedFormat::Mp3));
+        assert_eq!(sniff(&[0xff, 0xf1, 0x50, 0x80]), None);
+    }
+
+    #[test]
+    fn ogg_codecs_are_told_apart() {
+        let cases: [(&[u8], SniffedFormat); 4] = [
+            (b"\x01vorbis\0\0\0\0", SniffedFormat::OggVorbis),
+            (b"OpusHead\x01\x02", SniffedFormat::OggOpus),
+            (b"\x7fFLAC\x01\0", SniffedFormat::OggFlac),
+            (b"Speex   1.2", SniffedFormat::OggSpeex),
+        ];
+        for (packet, format) in cases {
+            assert_eq!(sniff(&ogg(packet)), Some(format), "{packet:?}");
+        }
+        assert_eq!(sniff(&ogg(b"\x80theora")), None);
+    }
+
+    #[test]
+    fn truncated_or_unknown_headers_are_not_recognised() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"RIFF"), None);
+        assert_eq!(sniff(b"OggS\0\0"), None);
+        assert_eq!(sniff(b"not audio at all"), None);
+    }
+
+    #[test]
+    fn only_symphonia_formats_are_decodable() {
+        assert!(SniffedFormat::OggVorbis.decodable());
+        assert!(!SniffedFormat::OggOpus.decodable());
+        assert!(!SniffedFormat::Matroska.decodable());
+    }
+
+    #[test]
+    fn reader_is_rewound_after_sniffing() {
+        let mut reader = Cursor::new(b"fLaC\0\0\0\x22rest".to_vec());
+        assert_eq!(
+            sniff_reader(&mut reader).unwrap(),
+            Some(SniffedFormat::Flac)
+        );
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn bundled_song_sniffs_as_mp3() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("songs/cone.mp3");
+        let mut file = File::open(path).unwrap();
+        assert_eq!(sniff_reader(&mut file).unwrap(), Some(SniffedFormat::Mp3));
+    }
+}
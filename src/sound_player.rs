@@ -1,14 +1,85 @@
-use rodio::{OutputStream, Sink};
-use std::fs::File;
-use std::io::BufReader;
+use crate::backend::{
+    AudioBackend, AudioSink, LoadOptions, RodioBackend, TrackSource, file_error, open_audio_file,
+    open_file,
+};
+use crate::effects::{EqualizerGains, MAX_EQ_GAIN_DB};
+use crate::http_stream::is_url;
+use crate::loudness::{DEFAULT_TARGET_LUFS, normalization_gain};
+use crate::metadata::{
+    AudioFormat, TrackMetadata, read_inline_metadata, read_inline_stream_info, read_metadata,
+    read_stream_info,
+};
+use crate::meter::Levels;
+use crate::playlist::{is_wildcard, list_tracks, parse_m3u};
+use crate::recording::{Recording, record_to_wav};
+use crate::stretch::{MAX_TEMPO, MIN_TEMPO};
+use crate::track_gains::{MAX_TRACK_GAIN_DB, TrackGains};
+use data_encoding::BASE64;
+use log::{Level, debug, info, warn};
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{
+    Arc, Mutex, PoisonError,
+    atomic::{AtomicBool, Ordering},
+};
+use std::thread;
 use std::time::Duration;
 use thiserror::Error;
 
+/// Upper bound for `volume`; values above 1.0 amplify beyond unity gain.
+pub const MAX_VOLUME: f32 = 3.0;
+
+/// Slowest speed that `speed_clamped` settles on; `speed` itself accepts anything
+/// above zero.
+pub const MIN_SPEED: f32 = 0.01;
+
+/// Default for `PlayerOptions::max_speed`. Much faster playback is mostly noise
+/// and costs resampling CPU in proportion.
+pub const MAX_SPEED: f32 = 4.0;
+
+/// How long before the end of a track the next queued one is preloaded.
+const PRELOAD_AHEAD: Duration = Duration::from_secs(5);
+
+/// Default for `PlayerOptions::max_inline_bytes`.
+pub const DEFAULT_MAX_INLINE_BYTES: usize = 16 << 20;
+
+/// Default for `PlayerOptions::background_load_bytes`, about six minutes of CD
+/// quality WAV.
+pub const DEFAULT_BACKGROUND_LOAD_BYTES: u64 = 64 << 20;
+
+/// Default for `PlayerOptions::history_len`.
+pub const DEFAULT_HISTORY_LEN: usize = 50;
+
+/// File extensions (lowercase) that `play` accepts.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["mp3", "wav", "flac", "ogg"];
+
+/// Interval between volume steps while fading out.
+const FADE_STEP: Duration = Duration::from_millis(20);
+
+/// Default for `PlayerOptions::volume_ramp`.
+pub const DEFAULT_VOLUME_RAMP: Duration = Duration::from_millis(50);
+
+/// Interval between volume steps of a ramp; ramps are short, so this is finer
+/// than `FADE_STEP`.
+const RAMP_STEP: Duration = Duration::from_millis(5);
+
 #[derive(Error, Debug)]
 pub enum SoundPlayerError {
     #[error("No song currently loaded")]
     NoSongLoaded,
 
+    #[error("Audio file not found: {file}")]
+    FileNotFound { file: String },
+
+    #[error("Permission denied opening audio file: {file}")]
+    PermissionDenied { file: String },
+
     #[error("Failed to open audio file: {file}")]
     FileOpenError {
         file: String,
@@ -16,6 +87,18 @@ pub enum SoundPlayerError {
         source: std::io::Error,
     },
 
+    #[error("Unsupported audio format '{ext}': {file}")]
+    UnsupportedFormat { file: String, ext: String },
+
+    #[error("Invalid inline audio: {reason}")]
+    InvalidInlineAudio { reason: String },
+
+    #[error("Inline audio of {size} bytes exceeds the limit of {limit} bytes")]
+    InlineAudioTooLarge { size: usize, limit: usize },
+
+    #[error("Cannot play '{file}' in reverse: {reason}")]
+    CannotReverse { file: String, reason: String },
+
     #[error("Failed to decode audio file: {file}")]
     DecodingError {
         file: String,
@@ -23,79 +106,1131 @@ pub enum SoundPlayerError {
         source: rodio::decoder::DecoderError,
     },
 
+    #[error("Failed to stream {url}: {reason}")]
+    NetworkError { url: String, reason: String },
+
     #[error("Audio stream error")]
     StreamError(#[from] rodio::StreamError),
 
     #[error("Seek operation failed: seeking to {position}s")]
     SeekError {
-        position: u64,
+        position: f64,
         #[source]
         source: rodio::source::SeekError,
     },
 
-    #[error("Failed to play audio file: {file}")]
-    PlayError {
+    #[error("Cannot seek to {position}s: the track is only {duration}s long")]
+    SeekOutOfRange { position: f64, duration: f64 },
+
+    #[error("Invalid seek position: {percent}% (must be between 0 and 100)")]
+    InvalidSeekPercent { percent: f64 },
+
+    #[error("The length of the current track is unknown")]
+    DurationUnknown,
+
+    #[error("Invalid volume level: {volume} (must be between 0.0 and {MAX_VOLUME})")]
+    InvalidVolume { volume: f32 },
+
+    #[error("Invalid speed: {speed} (must be greater than 0.0 and at most {max})")]
+    InvalidSpeed { speed: f32, max: f32 },
+
+    #[error(
+        "Invalid tempo: {tempo} (must be between {MIN_TEMPO} and {MAX_TEMPO}; time-stretching \
+         quality and CPU cost degrade quickly beyond that)"
+    )]
+    InvalidTempo { tempo: f32 },
+
+    #[error("Invalid balance: {pan} (must be between -1.0 and 1.0)")]
+    InvalidBalance { pan: f32 },
+
+    #[error("Invalid equalizer gain: {gain} dB (must be within ±{MAX_EQ_GAIN_DB} dB)")]
+    InvalidEqualizerGain { gain: f32 },
+
+    #[error("Invalid track gain: {gain} dB (must be within ±{MAX_TRACK_GAIN_DB} dB)")]
+    InvalidTrackGain { gain: f32 },
+
+    #[error("Metadata of '{song}' is unavailable: it's a network stream")]
+    MetadataUnavailable { song: String },
+
+    #[error("Failed to read metadata of: {file}")]
+    MetadataError {
         file: String,
         #[source]
-        source: rodio::PlayError,
+        source: symphonia::core::errors::Error,
     },
 
-    #[error("Invalid volume level: {volume} (must be between 0.0 and 1.0)")]
-    InvalidVolume { volume: f32 },
+    #[error("Failed to access state file {file}")]
+    StateFileError {
+        file: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("State file {file} is not valid")]
+    InvalidStateFile {
+        file: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("No state file given and none configured")]
+    NoStateFile,
+
+    #[error("Failed to save track gains to {file}")]
+    TrackGainsFileError {
+        file: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Invalid playlist {file}: {reason}")]
+    InvalidPlaylist { file: String, reason: String },
+
+    #[error("The playback queue is empty")]
+    QueueEmpty,
+
+    #[error("Failed to record to {file}")]
+    RecordingError {
+        file: String,
+        #[source]
+        source: io::Error,
+    },
 
-    #[error("Invalid speed: {speed} (must be greater than 0.0)")]
-    InvalidSpeed { speed: f32 },
+    #[error("No previously played track")]
+    HistoryEmpty,
+
+    #[error("No queue entry at index {index} (the queue has {len})")]
+    QueueIndexOutOfRange { index: usize, len: usize },
+
+    #[error("Invalid position: {position} seconds")]
+    InvalidPosition { position: f64 },
+
+    #[error("Invalid sleep timer: {minutes} minutes")]
+    InvalidSleepTimer { minutes: f64 },
+
+    #[error("No output device named '{name}'")]
+    DeviceNotFound { name: String },
+
+    #[error("'{song}' has already played to its end")]
+    TrackFinished { song: String },
+
+    #[error("No output devices to switch to")]
+    NoOutputDevices,
+
+    #[error("Failed to enumerate audio devices")]
+    DeviceEnumerationError(#[from] rodio::DevicesError),
 
     #[error("Stream handle is no longer valid")]
     InvalidStreamHandle,
+
+    #[error("Cannot create another player: at most {limit} are allowed")]
+    TooManyPlayers { limit: usize },
+}
+
+impl SoundPlayerError {
+    /// Stable, machine-readable identifier for the error variant.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SoundPlayerError::NoSongLoaded => "no_song_loaded",
+            SoundPlayerError::FileNotFound { .. } => "file_not_found",
+            SoundPlayerError::PermissionDenied { .. } => "permission_denied",
+            SoundPlayerError::FileOpenError { .. } => "file_open_error",
+            SoundPlayerError::UnsupportedFormat { .. } => "unsupported_format",
+            SoundPlayerError::InvalidInlineAudio { .. } => "invalid_inline_audio",
+            SoundPlayerError::InlineAudioTooLarge { .. } => "inline_audio_too_large",
+            SoundPlayerError::CannotReverse { .. } => "cannot_reverse",
+            SoundPlayerError::DecodingError { .. } => "decoding_error",
+            SoundPlayerError::NetworkError { .. } => "network_error",
+            SoundPlayerError::StreamError(_) => "stream_error",
+            SoundPlayerError::SeekError { .. } => "seek_error",
+            SoundPlayerError::SeekOutOfRange { .. } => "seek_out_of_range",
+            SoundPlayerError::InvalidSeekPercent { .. } => "invalid_seek_percent",
+            SoundPlayerError::DurationUnknown => "duration_unknown",
+            SoundPlayerError::InvalidVolume { .. } => "invalid_volume",
+            SoundPlayerError::InvalidSpeed { .. } => "invalid_speed",
+            SoundPlayerError::InvalidTempo { .. } => "invalid_tempo",
+            SoundPlayerError::InvalidBalance { .. } => "invalid_balance",
+            SoundPlayerError::InvalidEqualizerGain { .. } => "invalid_equalizer_gain",
+            SoundPlayerError::InvalidTrackGain { .. } => "invalid_track_gain",
+            SoundPlayerError::MetadataUnavailable { .. } => "metadata_unavailable",
+            SoundPlayerError::MetadataError { .. } => "metadata_error",
+            SoundPlayerError::StateFileError { .. } => "state_file_error",
+            SoundPlayerError::InvalidStateFile { .. } => "invalid_state_file",
+            SoundPlayerError::NoStateFile => "no_state_file",
+            SoundPlayerError::TrackGainsFileError { .. } => "track_gains_file_error",
+            SoundPlayerError::InvalidPlaylist { .. } => "invalid_playlist",
+            SoundPlayerError::QueueEmpty => "queue_empty",
+            SoundPlayerError::HistoryEmpty => "history_empty",
+            SoundPlayerError::RecordingError { .. } => "recording_error",
+            // Reported like a malformed order: the index itself is what's wrong.
+            SoundPlayerError::QueueIndexOutOfRange { .. } => "invalid_parameters",
+            SoundPlayerError::InvalidPosition { .. } => "invalid_parameters",
+            SoundPlayerError::InvalidSleepTimer { .. } => "invalid_parameters",
+            SoundPlayerError::DeviceNotFound { .. } => "device_not_found",
+            SoundPlayerError::TrackFinished { .. } => "track_finished",
+            SoundPlayerError::NoOutputDevices => "no_output_devices",
+            SoundPlayerError::DeviceEnumerationError(_) => "device_enumeration_error",
+            SoundPlayerError::InvalidStreamHandle => "invalid_stream_handle",
+            SoundPlayerError::TooManyPlayers { .. } => "too_many_players",
+        }
+    }
+
+    /// How seriously to log the error: failures on this side are errors, while
+    /// requests that can't be carried out as asked are warnings.
+    pub fn level(&self) -> Level {
+        match self {
+            SoundPlayerError::FileOpenError { .. }
+            | SoundPlayerError::DecodingError { .. }
+            | SoundPlayerError::StreamError(_)
+            | SoundPlayerError::SeekError { .. }
+            | SoundPlayerError::MetadataError { .. }
+            | SoundPlayerError::StateFileError { .. }
+            | SoundPlayerError::InvalidStateFile { .. }
+            | SoundPlayerError::TrackGainsFileError { .. }
+            | SoundPlayerError::RecordingError { .. }
+            | SoundPlayerError::DeviceEnumerationError(_)
+            | SoundPlayerError::InvalidStreamHandle => Level::Error,
+            _ => Level::Warn,
+        }
+    }
+
+    /// The error followed by each of its underlying causes, as told to clients.
+    pub fn message(&self) -> String {
+        let mut message = self.to_string();
+        let mut cause = std::error::Error::source(self);
+        while let Some(error) = cause {
+            message.push_str(&format!(": {}", error));
+            cause = error.source();
+        }
+        message
+    }
 }
 
 pub type SoundPlayerResult<T> = Result<T, SoundPlayerError>;
 
-pub struct SoundPlayer {
+/// Replaces a leading `~` with the user's home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    if path == "~" {
+        if let Some(home) = home() {
+            return home;
+        }
+    } else if let Some(rest) = path.strip_prefix("~/")
+        && let Some(home) = home()
+    {
+        return home.join(rest);
+    }
+    PathBuf::from(path)
+}
+
+/// Rejects files whose extension is not in `SUPPORTED_EXTENSIONS` (case-insensitive).
+pub fn check_extension(sound_file: &str) -> SoundPlayerResult<()> {
+    let ext = Path::new(sound_file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    if SUPPORTED_EXTENSIONS.contains(&ext.as_str()) {
+        Ok(())
+    } else {
+        Err(SoundPlayerError::UnsupportedFormat {
+            file: sound_file.to_string(),
+            ext,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+impl FromStr for RepeatMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(RepeatMode::Off),
+            "one" => Ok(RepeatMode::One),
+            "all" => Ok(RepeatMode::All),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `secs` as a `Duration`, or `InvalidPosition` if it's negative, not finite
+/// or too large to represent.
+fn to_duration(secs: f64) -> SoundPlayerResult<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .map_err(|_| SoundPlayerError::InvalidPosition { position: secs })
+}
+
+/// What `resume` does once the current track has played to its end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResumeFinished {
+    /// Starts the track again from the beginning.
+    #[default]
+    Restart,
+    /// Fails with `TrackFinished`, leaving it to the client what to play next.
+    Report,
+}
+
+impl FromStr for ResumeFinished {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "restart" => Ok(ResumeFinished::Restart),
+            "report" => Ok(ResumeFinished::Report),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a volume fade moves between its start and end levels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Starts slowly and speeds up; sounds even when fading in.
+    Exponential,
+    /// Starts quickly and slows down; sounds even when fading out.
+    Logarithmic,
+}
+
+impl FadeCurve {
+    /// Fraction of the way from the start to the end level once `progress`
+    /// (0.0 to 1.0) of the fade's time has passed.
+    fn ease(self, progress: f32) -> f32 {
+        match self {
+            FadeCurve::Linear => progress,
+            // 2^10 - 1: about 60 dB between the first step and the last.
+            FadeCurve::Exponential => (2f32.powf(10.0 * progress) - 1.0) / 1023.0,
+            FadeCurve::Logarithmic => (1.0 + 1023.0 * progress).log2() / 10.0,
+        }
+    }
+}
+
+impl FromStr for FadeCurve {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "linear" => Ok(FadeCurve::Linear),
+            "exponential" | "exp" => Ok(FadeCurve::Exponential),
+            "logarithmic" | "log" => Ok(FadeCurve::Logarithmic),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Properties of the audio being played; unknown fields are `None`.
+#[derive(Serialize, Debug, Clone)]
+pub struct AudioInfo {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub codec: Option<String>,
+}
+
+/// Where playback is in the current track. The fields that need the track's
+/// length are `None` when it isn't known.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TimeInfo {
+    pub elapsed_secs: f64,
+    pub remaining_secs: Option<f64>,
+    pub duration_secs: Option<f64>,
+}
+
+/// Where output goes and whether it still can.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DeviceStatus {
+    /// `None` for the default output device.
+    pub device: Option<String>,
+    pub healthy: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PlayerSnapshot {
+    pub current_song: Option<String>,
+    pub is_playing: bool,
+    pub is_paused: bool,
+    /// Track volume, before the master volume is applied.
+    pub volume: Option<f32>,
+    pub master_volume: f32,
+    pub position_secs: Option<f64>,
+    pub duration_secs: Option<f64>,
+    pub repeat_mode: RepeatMode,
+    /// Times the current track will still be replayed before playback moves on.
+    pub repeats_remaining: Option<u32>,
+}
+
+/// What `save_state` writes and `load_state` restores.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SessionState {
+    /// Track that was playing; `None` if nothing was, or it had finished.
+    pub song: Option<String>,
+    pub position_secs: f64,
+    pub paused: bool,
+    pub volume: f32,
+    pub master_volume: f32,
+    pub speed: f32,
+    pub queue: Vec<String>,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            song: None,
+            position_secs: 0.0,
+            paused: false,
+            volume: 1.0,
+            master_volume: 1.0,
+            speed: 1.0,
+            queue: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerOptions {
+    /// Directory that relative song names are resolved against.
+    pub base_dir: PathBuf,
+    /// Where session state is auto-saved and restored from; `None` disables both.
+    pub state_file: Option<PathBuf>,
+    /// Largest decoded payload `play_inline` accepts, in bytes.
+    pub max_inline_bytes: usize,
+    /// Files at least this large are opened in the background when played; 0
+    /// opens every file before `play` returns.
+    pub background_load_bytes: u64,
+    /// How many recently played tracks are remembered; 0 disables the history.
+    pub history_len: usize,
+    /// How long `volume` takes to glide to a new level; zero applies it at once.
+    pub volume_ramp: Duration,
+    /// Fastest speed `speed` accepts.
+    pub max_speed: f32,
+    /// Track volume the player starts at, before any `volume` command.
+    pub default_volume: f32,
+    /// Loudness, in LUFS, that normalization brings tracks to.
+    pub target_lufs: f32,
+    /// How long the last track fades out before the queue runs dry; zero lets
+    /// it end as recorded.
+    pub end_fade: Duration,
+    /// Where gains set with `set_track_gain` are saved and loaded from; `None`
+    /// keeps them in memory only.
+    pub track_gains_file: Option<PathBuf>,
+    /// Devices `cycle_device` rotates through, in order; empty for all of them.
+    pub preferred_devices: Vec<String>,
+    /// What `resume` does with a track that has finished.
+    pub resume_finished: ResumeFinished,
+}
+
+impl Default for PlayerOptions {
+    fn default() -> Self {
+        Self {
+            base_dir: PathBuf::new(),
+            state_file: None,
+            max_inline_bytes: DEFAULT_MAX_INLINE_BYTES,
+            background_load_bytes: DEFAULT_BACKGROUND_LOAD_BYTES,
+            history_len: DEFAULT_HISTORY_LEN,
+            volume_ramp: DEFAULT_VOLUME_RAMP,
+            max_speed: MAX_SPEED,
+            default_volume: 1.0,
+            target_lufs: DEFAULT_TARGET_LUFS,
+            end_fade: Duration::ZERO,
+            track_gains_file: None,
+            preferred_devices: Vec::new(),
+            resume_finished: ResumeFinished::default(),
+        }
+    }
+}
+
+pub struct SoundPlayer<B: AudioBackend = RodioBackend> {
     current_song: String,
-    stream_handle: OutputStream,
-    sink: Option<Sink>,
+    /// Where `current_song` was loaded from, for replays.
+    current_source: Option<TrackSource>,
+    backend: B,
+    /// Shared so volume ramps can run on their own thread.
+    sink: Option<Arc<B::Sink>>,
+    /// Track volume to return to on unmute; `Some` while muted.
+    muted_volume: Option<f32>,
+    /// Length of the current track, read from the decoder once when it's loaded
+    /// and cleared on stop. Duration queries and seek validation use this rather
+    /// than reopening the file.
+    current_duration: Option<Duration>,
+    /// Sample rate and channel count of the current track, captured at load time.
+    current_format: Option<AudioFormat>,
+    /// Whether the current track's source can seek, captured at load time.
+    current_seekable: bool,
+    /// Volume of the track, set by `volume`; the sink plays at this times
+    /// `master_volume`.
+    last_volume: f32,
+    master_volume: f32,
+    last_speed: f32,
+    last_tempo: f32,
+    last_pan: f32,
+    /// Whether tracks are downmixed to mono; kept across track changes.
+    mono: bool,
+    equalizer: EqualizerGains,
+    queue: VecDeque<String>,
+    repeat_mode: RepeatMode,
+    fade_out_cancel: Option<Arc<AtomicBool>>,
+    /// Overlap between consecutive queued tracks; zero plays them back to back.
+    crossfade: Duration,
+    /// Fade-out at the end of the last track, set by `set_end_fade`.
+    end_fade: Duration,
+    /// Whether the current track is fading out under `end_fade`.
+    end_fading: bool,
+    volume_ramp: Duration,
+    volume_ramp_cancel: Option<Arc<AtomicBool>>,
+    device_name: Option<String>,
+    preferred_devices: Vec<String>,
+    resume_finished: ResumeFinished,
+    base_dir: PathBuf,
+    state_file: Option<PathBuf>,
+    max_inline_bytes: usize,
+    background_load_bytes: u64,
+    /// Bumped whenever a track is loaded or stopped, so a background load that
+    /// finishes afterwards knows it was superseded.
+    load_generation: u64,
+    /// Generation of the last background load begun; it's still under way while
+    /// this equals `load_generation`.
+    background_generation: Option<u64>,
+    /// Tracks started as the current track, oldest first; the last entry is the
+    /// current or most recent one.
+    history: VecDeque<String>,
+    history_len: usize,
+    max_speed: f32,
+    overlays: Vec<B::Sink>,
+    /// Set once the end of the current track has been reported by `take_finished`.
+    finish_reported: bool,
+    /// Replays of the current track still due, set by `set_repeat_count`. They
+    /// take precedence over the repeat mode; loading another track clears them.
+    repeats_left: u32,
+    /// Region (start, end) that playback keeps jumping back into.
+    ab_loop: Option<(Duration, Duration)>,
+    preload: Option<Preload<B::Prepared>>,
+    /// Recording of the current track, if one was started.
+    recording: Option<Recording>,
+    /// Whether the levels of the current track are measured; carried over to
+    /// tracks loaded later.
+    metering: bool,
+    /// Whether tracks are brought to `target_lufs`, set by `set_normalize`.
+    normalize: bool,
+    target_lufs: f32,
+    /// Integrated loudness of every file measured so far; `None` for files that
+    /// couldn't be measured, so they aren't decoded again either.
+    loudness_cache: HashMap<PathBuf, Option<f64>>,
+    /// Normalization and hand-set gain of the current track, on top of its
+    /// volume.
+    track_gain: f32,
+    /// Gains set by hand per track; players forked off the default one share it.
+    track_gains: Arc<Mutex<TrackGains>>,
+}
+
+/// Progress of preparing the next queued track ahead of time.
+enum Preload<P> {
+    Pending(String),
+    Ready(String, P),
+    Failed(String),
 }
 
-impl SoundPlayer {
-    pub fn new() -> SoundPlayerResult<Self> {
-        let stream_handle = rodio::OutputStreamBuilder::open_default_stream()
-            .map_err(SoundPlayerError::StreamError)?;
+impl<P> Preload<P> {
+    fn song(&self) -> &str {
+        match self {
+            Preload::Pending(song) | Preload::Ready(song, _) | Preload::Failed(song) => song,
+        }
+    }
+}
 
-        Ok(Self {
+impl<B: AudioBackend> SoundPlayer<B> {
+    pub fn new(backend: B, options: PlayerOptions) -> Self {
+        Self {
             current_song: String::new(),
-            stream_handle,
+            current_source: None,
+            backend,
             sink: None,
+            muted_volume: None,
+            current_duration: None,
+            current_format: None,
+            current_seekable: false,
+            last_volume: options.default_volume,
+            master_volume: 1.0,
+            last_speed: 1.0,
+            last_tempo: 1.0,
+            last_pan: 0.0,
+            mono: false,
+            equalizer: EqualizerGains::default(),
+            queue: VecDeque::new(),
+            repeat_mode: RepeatMode::default(),
+            fade_out_cancel: None,
+            crossfade: Duration::ZERO,
+            end_fade: options.end_fade,
+            end_fading: false,
+            volume_ramp: options.volume_ramp,
+            volume_ramp_cancel: None,
+            device_name: None,
+            preferred_devices: options.preferred_devices,
+            resume_finished: options.resume_finished,
+            base_dir: options.base_dir,
+            state_file: options.state_file,
+            max_inline_bytes: options.max_inline_bytes,
+            background_load_bytes: options.background_load_bytes,
+            load_generation: 0,
+            background_generation: None,
+            history: VecDeque::new(),
+            history_len: options.history_len,
+            max_speed: options.max_speed,
+            overlays: Vec::new(),
+            finish_reported: false,
+            repeats_left: 0,
+            ab_loop: None,
+            preload: None,
+            recording: None,
+            metering: false,
+            normalize: false,
+            target_lufs: options.target_lufs,
+            loudness_cache: HashMap::new(),
+            track_gain: 1.0,
+            track_gains: Arc::new(Mutex::new(TrackGains::load(options.track_gains_file))),
+        }
+    }
+
+    /// A backend for another player, sharing this one's output device.
+    pub fn fork_backend(&self) -> SoundPlayerResult<B> {
+        self.backend.fork()
+    }
+
+    /// Names of the output devices the backend can switch to.
+    pub fn list_devices(&self) -> SoundPlayerResult<Vec<String>> {
+        self.backend.list_devices()
+    }
+
+    /// The output device in use and whether its stream is believed healthy.
+    pub fn device_status(&self) -> DeviceStatus {
+        DeviceStatus {
+            device: self.backend.device_name(),
+            healthy: self.backend.stream_healthy(),
+        }
+    }
+
+    /// Moves output to the device called `name`. A loaded track is restarted on
+    /// the new device at its previous position and pause state.
+    pub fn set_device(&mut self, name: &str) -> SoundPlayerResult<()> {
+        let resume_at = self.resume_point();
+        self.backend.set_device(name)?;
+        self.device_name = Some(name.to_string());
+        self.restore(resume_at)
+    }
+
+    /// Moves output to the device after the current one among the preferred
+    /// devices that are connected, or among all devices if none are configured,
+    /// wrapping around at the end. Playback carries on as with `set_device`.
+    /// Returns the device switched to.
+    pub fn cycle_device(&mut self) -> SoundPlayerResult<String> {
+        let available = self.backend.list_devices()?;
+        let candidates: Vec<String> = if self.preferred_devices.is_empty() {
+            available
+        } else {
+            self.preferred_devices
+                .iter()
+                .filter(|name| available.contains(name))
+                .cloned()
+                .collect()
+        };
+        let current = self.backend.device_name();
+        let next = candidates
+            .iter()
+            .position(|name| Some(name) == current.as_ref())
+            .map_or(0, |index| (index + 1) % candidates.len());
+        let name = candidates
+            .get(next)
+            .cloned()
+            .ok_or(SoundPlayerError::NoOutputDevices)?;
+        self.set_device(&name)?;
+        Ok(name)
+    }
+
+    /// Rebuilds the output stream after the device behind it went away, restarting
+    /// a loaded track where it was. Overlays are dropped.
+    pub fn reopen_stream(&mut self) -> SoundPlayerResult<()> {
+        let resume_at = self.resume_point();
+        self.overlays.clear();
+        self.backend.reopen()?;
+        info!("Reopened the audio output stream");
+        self.restore(resume_at)
+    }
+
+    /// Position and pause state of the current track, if one is loaded.
+    fn resume_point(&self) -> Option<(Duration, bool)> {
+        self.sink
+            .as_ref()
+            .filter(|sink| !sink.empty())
+            .map(|sink| (sink.get_pos(), sink.is_paused()))
+    }
+
+    /// Reloads the current track and restores a position saved by `resume_point`.
+    fn restore(&mut self, resume_at: Option<(Duration, bool)>) -> SoundPlayerResult<()> {
+        if let Some((position, paused)) = resume_at {
+            self.replay()?;
+            let sink = self.get_sink()?;
+            if paused {
+                sink.pause();
+            }
+            if let Err(e) = sink.try_seek(position) {
+                warn!("Could not restore playback position: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves a song name against the base directory; absolute paths (after `~`
+    /// expansion) are used as-is.
+    fn resolve_path(&self, sound_file: &str) -> PathBuf {
+        let path = expand_tilde(sound_file);
+        if path.is_absolute() {
+            path
+        } else {
+            self.base_dir.join(path)
+        }
+    }
+
+    /// Resolves a song name to a URL to stream or a file to open; files must have a
+    /// supported extension.
+    fn resolve_source(&self, sound_file: &str) -> SoundPlayerResult<TrackSource> {
+        if is_url(sound_file) {
+            return Ok(TrackSource::Url(sound_file.to_string()));
+        }
+        check_extension(sound_file)?;
+        Ok(TrackSource::File(self.resolve_path(sound_file)))
+    }
+
+    fn get_sink(&self) -> SoundPlayerResult<&B::Sink> {
+        self.sink.as_deref().ok_or(SoundPlayerError::NoSongLoaded)
+    }
+
+    /// Volume a newly created sink should start at: the track volume scaled by
+    /// the master volume, honouring mute.
+    fn output_volume(&self) -> f32 {
+        if self.muted_volume.is_some() {
+            0.0
+        } else {
+            self.last_volume * self.master_volume
+        }
+    }
+
+    /// Volume the current track's sink plays at: `output_volume` with the
+    /// track's normalization gain applied.
+    fn track_volume(&self) -> f32 {
+        self.output_volume() * self.track_gain
+    }
+
+    /// Gain that brings `source` to the target loudness while normalization is
+    /// on; 1.0 otherwise, and for sources other than files. Each file is
+    /// decoded for measuring only the first time.
+    fn normalization_gain(&mut self, source: &TrackSource) -> f32 {
+        if !self.normalize {
+            return 1.0;
+        }
+        let (TrackSource::File(path) | TrackSource::Reversed(path)) = source else {
+            return 1.0;
+        };
+        let loudness = match self.loudness_cache.get(path) {
+            Some(&loudness) => loudness,
+            None => {
+                let loudness = B::measure_loudness(&TrackSource::File(path.clone()))
+                    .unwrap_or_else(|e| {
+                        warn!(
+                            "Could not measure the loudness of {}: {}",
+                            path.display(),
+                            e
+                        );
+                        None
+                    });
+                if let Some(loudness) = loudness {
+                    debug!("{} measured at {:.1} LUFS", path.display(), loudness);
+                }
+                self.loudness_cache.insert(path.clone(), loudness);
+                loudness
+            }
+        };
+        loudness.map_or(1.0, |loudness| {
+            normalization_gain(loudness, self.target_lufs)
         })
     }
 
-    fn get_sink(&self) -> SoundPlayerResult<&Sink> {
-        self.sink.as_ref().ok_or(SoundPlayerError::NoSongLoaded)
+    /// Turns loudness normalization on or off, for the current track (ramped
+    /// like `volume`) and the ones after it. Returns the gain now applied to the
+    /// current track in dB; 0 when nothing is loaded.
+    pub fn set_normalize(&mut self, enabled: bool) -> f32 {
+        self.normalize = enabled;
+        let mut gain = 1.0;
+        if let (Some(sink), Some(source)) = (self.sink.clone(), self.current_source.clone()) {
+            gain = self.normalization_gain(&source);
+            self.track_gain = gain * self.manual_gain(&source);
+            self.ramp_sink(
+                sink,
+                self.track_volume(),
+                self.volume_ramp,
+                FadeCurve::Linear,
+            );
+        }
+        20.0 * gain.log10()
     }
 
-    pub fn play(&mut self, sound_file: &str) -> SoundPlayerResult<()> {
-        if let Some(ref sink) = self.sink {
+    /// Key `source` is remembered under in the track gains; inline audio has
+    /// none.
+    fn track_gain_key(source: &TrackSource) -> Option<String> {
+        match source {
+            TrackSource::File(path) | TrackSource::Reversed(path) => {
+                Some(path.display().to_string())
+            }
+            TrackSource::Url(url) => Some(url.clone()),
+            TrackSource::Inline { .. } => None,
+        }
+    }
+
+    /// Linear gain set by hand for `source`; 1.0 if it has none.
+    fn manual_gain(&self, source: &TrackSource) -> f32 {
+        Self::track_gain_key(source).map_or(1.0, |key| {
+            let db = self
+                .track_gains
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&key);
+            10f32.powf(db / 20.0)
+        })
+    }
+
+    /// Remembers a gain offset in dB for `song_name`, applied on top of its
+    /// volume (and normalization) whenever it's played; 0 forgets it. Takes
+    /// effect at once if the track is the current one, and is saved to the
+    /// configured track gains file.
+    pub fn set_track_gain(&mut self, song_name: &str, db: f32) -> SoundPlayerResult<()> {
+        if !(-MAX_TRACK_GAIN_DB..=MAX_TRACK_GAIN_DB).contains(&db) {
+            return Err(SoundPlayerError::InvalidTrackGain { gain: db });
+        }
+        let source = self.resolve_source(song_name)?;
+        let Some(key) = Self::track_gain_key(&source) else {
+            return Ok(());
+        };
+        {
+            let mut gains = self
+                .track_gains
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            gains
+                .set(key.clone(), db)
+                .map_err(|source| SoundPlayerError::TrackGainsFileError {
+                    file: gains
+                        .file()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_default(),
+                    source,
+                })?;
+        }
+        let current = self.current_source.clone();
+        if let (Some(sink), Some(current)) = (self.sink.clone(), current)
+            && Self::track_gain_key(&current).as_deref() == Some(key.as_str())
+        {
+            self.track_gain = self.normalization_gain(&current) * self.manual_gain(&current);
+            self.ramp_sink(
+                sink,
+                self.track_volume(),
+                self.volume_ramp,
+                FadeCurve::Linear,
+            );
+        }
+        Ok(())
+    }
+
+    /// Lets this player use the same hand-set track gains as `other`.
+    pub fn share_track_gains(&mut self, other: &SoundPlayer<B>) {
+        self.track_gains = other.track_gains.clone();
+    }
+
+    /// Plays `sound_file` on its own sink, mixed over whatever else is playing.
+    /// Finished overlays are dropped by `reap_overlays` and whenever a new one
+    /// starts.
+    pub fn play_overlay(&mut self, sound_file: &str) -> SoundPlayerResult<()> {
+        let source = self.resolve_source(sound_file)?;
+        let track = self.backend.load(
+            &source,
+            LoadOptions {
+                volume: self.output_volume(),
+                speed: 1.0,
+                tempo: 1.0,
+                pan: self.last_pan,
+                mono: self.mono,
+                equalizer: self.equalizer,
+                fade_in: None,
+                metering: false,
+                paused: false,
+            },
+        )?;
+
+        self.reap_overlays();
+        self.overlays.push(track.sink);
+        Ok(())
+    }
+
+    /// Drops overlays that have played to their end and returns how many there
+    /// were, so a long soundboard session doesn't accumulate dead sinks.
+    pub fn reap_overlays(&mut self) -> usize {
+        let before = self.overlays.len();
+        self.overlays.retain(|sink| !sink.empty());
+        before - self.overlays.len()
+    }
+
+    /// Sinks still playing or paused: the current track's plus the overlays'.
+    pub fn active_sink_count(&self) -> usize {
+        let current = self.sink.as_ref().is_some_and(|sink| !sink.empty());
+        usize::from(current) + self.overlays.iter().filter(|sink| !sink.empty()).count()
+    }
+
+    pub fn stop_overlays(&mut self) {
+        for sink in self.overlays.drain(..) {
             sink.stop();
-            self.sink = None;
         }
+    }
 
-        let file = File::open(sound_file).map_err(|e| SoundPlayerError::FileOpenError {
-            file: sound_file.to_string(),
-            source: e,
+    /// Stops the current track and every overlay at once, cancelling fades and
+    /// ramps that would otherwise keep a sink alive. Used on shutdown.
+    pub fn stop_everything(&mut self) {
+        self.load_generation += 1;
+        self.cancel_fade_out();
+        self.cancel_volume_ramp();
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        self.stop_overlays();
+    }
+
+    /// Loads and starts `sound_file`, optionally fading in from silence over `fade_in`.
+    pub fn play(&mut self, sound_file: &str, fade_in: Option<Duration>) -> SoundPlayerResult<()> {
+        let source = self.resolve_source(sound_file)?;
+        self.load_source(sound_file, source, None, fade_in)
+    }
+
+    /// If `sound_file` is a file of at least the background load size, checks
+    /// that it opens and returns the source to prepare off the lock and hand to
+    /// `finish_background_load`, with the generation of the load. Whatever was
+    /// playing carries on meanwhile. For anything else nothing is done and
+    /// `None` is returned, so the track is played as usual.
+    pub fn begin_background_load(
+        &mut self,
+        sound_file: &str,
+    ) -> SoundPlayerResult<Option<(TrackSource, u64)>> {
+        if self.background_load_bytes == 0 || is_url(sound_file) {
+            return Ok(None);
+        }
+        let source = self.resolve_source(sound_file)?;
+        let TrackSource::File(path) = &source else {
+            return Ok(None);
+        };
+        let file = open_audio_file(path)?;
+        if file.metadata().map_or(0, |metadata| metadata.len()) < self.background_load_bytes {
+            return Ok(None);
+        }
+        self.load_generation += 1;
+        self.background_generation = Some(self.load_generation);
+        Ok(Some((source, self.load_generation)))
+    }
+
+    /// Starts a track prepared for `begin_background_load`, unless another track
+    /// was loaded or playback stopped since. Returns whether it was started.
+    pub fn finish_background_load(
+        &mut self,
+        sound_file: &str,
+        source: TrackSource,
+        generation: u64,
+        prepared: SoundPlayerResult<B::Prepared>,
+        fade_in: Option<Duration>,
+    ) -> SoundPlayerResult<bool> {
+        if generation != self.load_generation {
+            return Ok(false);
+        }
+        self.load_source(sound_file, source, Some(prepared?), fade_in)?;
+        Ok(true)
+    }
+
+    /// Loads `sound_file` and starts it `position` seconds in. The track is held
+    /// paused until the seek is done, so its beginning is never heard. A position
+    /// past the end is rejected, leaving nothing playing.
+    pub fn play_from(&mut self, sound_file: &str, position: f64) -> SoundPlayerResult<()> {
+        let source = self.resolve_source(sound_file)?;
+        self.start_source(sound_file, source, None, None, true)?;
+        if let Err(e) = self.seek(position) {
+            self.stop()?;
+            return Err(e);
+        }
+        self.get_sink()?.play();
+        Ok(())
+    }
+
+    /// If `name` is a directory, or a file name with `*`/`?` wildcards (only in its
+    /// last component), plays the first matching audio file and queues the rest,
+    /// in sorted order. Returns the number of tracks matched, or `None` when `name`
+    /// is neither, in which case nothing is played.
+    pub fn play_matching(
+        &mut self,
+        name: &str,
+        fade_in: Option<Duration>,
+    ) -> SoundPlayerResult<Option<usize>> {
+        if is_url(name) {
+            return Ok(None);
+        }
+        let path = self.resolve_path(name);
+        let (dir, pattern) = match path.file_name().and_then(|file| file.to_str()) {
+            _ if path.is_dir() => (path.as_path(), None),
+            Some(file) if is_wildcard(file) => (path.parent().unwrap_or(Path::new("")), Some(file)),
+            _ => return Ok(None),
+        };
+        let mut tracks: VecDeque<String> = list_tracks(dir, pattern)
+            .map_err(|e| file_error(dir, e))?
+            .into();
+        let count = tracks.len();
+        let first = tracks.pop_front().ok_or(SoundPlayerError::FileNotFound {
+            file: path.display().to_string(),
         })?;
+        self.play(&first, fade_in)?;
+        self.queue.extend(tracks);
+        Ok(Some(count))
+    }
 
-        let buf_reader = BufReader::new(file);
+    /// Plays a file backwards. The whole file is decoded before playback starts,
+    /// so it can't be streamed and must be no longer than `MAX_REVERSE_DURATION`.
+    pub fn play_reverse(&mut self, song_name: &str) -> SoundPlayerResult<()> {
+        let TrackSource::File(path) = self.resolve_source(song_name)? else {
+            return Err(SoundPlayerError::CannotReverse {
+                file: song_name.to_string(),
+                reason: "network streams can't be reversed".to_string(),
+            });
+        };
+        self.load_source(song_name, TrackSource::Reversed(path), None, None)
+    }
 
-        let sink = rodio::play(&self.stream_handle.mixer(), buf_reader).map_err(|e| {
-            SoundPlayerError::PlayError {
-                file: sound_file.to_string(),
-                source: e,
+    /// Decodes base64-encoded audio in `format` (a file extension) and plays it.
+    /// Payloads whose decoded size would exceed the configured limit are
+    /// rejected before decoding.
+    pub fn play_inline(&mut self, data_base64: &str, format: &str) -> SoundPlayerResult<()> {
+        let name = format!("inline.{}", format.to_lowercase());
+        check_extension(&name)?;
+        let size = BASE64.decode_len(data_base64.len()).map_err(|e| {
+            SoundPlayerError::InvalidInlineAudio {
+                reason: e.to_string(),
+            }
+        })?;
+        if size > self.max_inline_bytes {
+            return Err(SoundPlayerError::InlineAudioTooLarge {
+                size,
+                limit: self.max_inline_bytes,
+            });
+        }
+        let data = BASE64.decode(data_base64.as_bytes()).map_err(|e| {
+            SoundPlayerError::InvalidInlineAudio {
+                reason: e.to_string(),
             }
         })?;
+        let source = TrackSource::Inline {
+            data: data.into(),
+            format: format.to_lowercase(),
+        };
+        self.load_source(&name, source, None, None)
+    }
+
+    /// Starts the current track again from the beginning, without resolving its
+    /// name again.
+    fn replay(&mut self) -> SoundPlayerResult<()> {
+        let source = self
+            .current_source
+            .clone()
+            .ok_or(SoundPlayerError::NoSongLoaded)?;
+        let song_name = self.current_song.clone();
+        let repeats_left = self.repeats_left;
+        self.load_source(&song_name, source, None, None)?;
+        self.repeats_left = repeats_left;
+        Ok(())
+    }
+
+    /// Starts `source` as the current track, using `prepared` if it was already
+    /// opened ahead of time.
+    fn load_source(
+        &mut self,
+        sound_file: &str,
+        source: TrackSource,
+        prepared: Option<B::Prepared>,
+        fade_in: Option<Duration>,
+    ) -> SoundPlayerResult<()> {
+        self.start_source(sound_file, source, prepared, fade_in, false)
+    }
+
+    /// Like `load_source`, with the new sink held paused from the start when
+    /// `paused` is set.
+    fn start_source(
+        &mut self,
+        sound_file: &str,
+        source: TrackSource,
+        prepared: Option<B::Prepared>,
+        fade_in: Option<Duration>,
+        paused: bool,
+    ) -> SoundPlayerResult<()> {
+        self.load_generation += 1;
+        self.cancel_fade_out();
+        self.cancel_volume_ramp();
+        // A recording covers one track; it's finalized once the old sink is gone.
+        self.recording = None;
+        if let Some(ref sink) = self.sink {
+            sink.stop();
+            self.sink = None;
+        }
 
-        self.sink = Some(sink);
+        let prepared = match prepared {
+            Some(prepared) => prepared,
+            None => B::prepare(&source)?,
+        };
+        self.track_gain = self.normalization_gain(&source) * self.manual_gain(&source);
+        let track = self.backend.start(
+            prepared,
+            LoadOptions {
+                volume: self.track_volume(),
+                speed: self.last_speed,
+                tempo: self.last_tempo,
+                pan: self.last_pan,
+                mono: self.mono,
+                equalizer: self.equalizer,
+                fade_in,
+                metering: self.metering,
+                paused,
+            },
+        )?;
+
+        self.sink = Some(Arc::new(track.sink));
+        if self.current_song != sound_file {
+            self.ab_loop = None;
+        }
+        // Replays (repeat-one, restarts) don't add another entry, and inline audio
+        // can't be played again by name.
+        if !matches!(source, TrackSource::Inline { .. })
+            && self.history.back().is_none_or(|last| last != sound_file)
+        {
+            self.history.push_back(sound_file.to_string());
+            let excess = self.history.len().saturating_sub(self.history_len);
+            self.history.drain(..excess);
+        }
         self.current_song = sound_file.to_string();
+        self.current_seekable = source.seekable();
+        self.current_source = Some(source);
+        self.current_duration = track.duration;
+        self.current_format = track.format;
+        self.finish_reported = false;
+        self.end_fading = false;
+        self.repeats_left = 0;
 
         Ok(())
     }
@@ -108,25 +1243,589 @@ impl SoundPlayer {
         Ok(())
     }
 
-    pub fn resume(&self) -> SoundPlayerResult<()> {
+    /// Continues a paused track. One that has already played to its end is
+    /// restarted or reported as `TrackFinished`, as configured; returns `true`
+    /// if it was restarted.
+    pub fn resume(&mut self) -> SoundPlayerResult<bool> {
         let sink = self.get_sink()?;
+        if sink.empty() {
+            return match self.resume_finished {
+                ResumeFinished::Restart => self.replay().map(|()| true),
+                ResumeFinished::Report => Err(SoundPlayerError::TrackFinished {
+                    song: self.current_song.clone(),
+                }),
+            };
+        }
         if sink.is_paused() {
             sink.play();
         }
-        Ok(())
+        Ok(false)
+    }
+
+    /// Pauses a playing track and resumes anything else, finished tracks
+    /// included. Returns `true` if a finished track was restarted.
+    pub fn toggle_pause(&mut self) -> SoundPlayerResult<bool> {
+        let sink = self.get_sink()?;
+        if sink.is_paused() || sink.empty() {
+            self.resume()
+        } else {
+            self.pause().map(|()| false)
+        }
     }
 
+    /// Stops the current track at once. A fade-out still running is cut short and
+    /// a track still loading in the background abandoned, so stopping succeeds
+    /// while either is all that's left of playback.
     pub fn stop(&mut self) -> SoundPlayerResult<()> {
+        let fading = self
+            .fade_out_cancel
+            .as_ref()
+            .is_some_and(|over| !over.load(Ordering::SeqCst));
+        let loading = self.background_generation == Some(self.load_generation);
+        self.cancel_fade_out();
+        match self.sink.take() {
+            Some(sink) => {
+                sink.stop();
+                self.cancel_volume_ramp();
+                self.current_song.clear();
+                self.current_duration = None;
+                self.current_format = None;
+            }
+            None if fading || loading => {}
+            None => return Err(SoundPlayerError::NoSongLoaded),
+        }
+        self.load_generation += 1;
+        Ok(())
+    }
+
+    /// Appends a song to the queue and returns the new queue length.
+    pub fn enqueue(&mut self, song_name: &str) -> usize {
+        self.queue.push_back(song_name.to_string());
+        self.queue.len()
+    }
+
+    /// Stops the current track and starts the next queued one. With `RepeatMode::All`
+    /// the current track is moved to the back of the queue first.
+    pub fn play_next(&mut self) -> SoundPlayerResult<()> {
+        self.advance(None)
+    }
+
+    fn advance(&mut self, fade_in: Option<Duration>) -> SoundPlayerResult<()> {
+        if self.requeues_current() {
+            self.queue.push_back(self.current_song.clone());
+        }
+        let song_name = self.queue.pop_front().ok_or(SoundPlayerError::QueueEmpty)?;
+        match self.preload.take() {
+            Some(Preload::Ready(name, prepared)) if name == song_name => {
+                debug!("Starting preloaded track '{}'", song_name);
+                let source = self.resolve_source(&song_name)?;
+                self.load_source(&song_name, source, Some(prepared), fade_in)
+            }
+            _ => self.play(&song_name, fade_in),
+        }
+    }
+
+    /// Sets how long consecutive queued tracks overlap; zero disables crossfading.
+    pub fn set_crossfade(&mut self, duration: Duration) {
+        self.crossfade = duration;
+    }
+
+    /// Once the current track is within the crossfade time of its end, starts the
+    /// next queued one fading in while the current one fades out. Returns the name
+    /// of the track that was faded out, if it did.
+    pub fn crossfade_if_due(&mut self) -> SoundPlayerResult<Option<String>> {
+        if self.crossfade.is_zero()
+            || self.repeats_left > 0
+            || self.repeat_mode == RepeatMode::One
+            || !self.can_advance()
+        {
+            return Ok(None);
+        }
+        let (Some(sink), Some(duration)) = (&self.sink, self.current_duration) else {
+            return Ok(None);
+        };
+        if sink.empty()
+            || sink.is_paused()
+            || duration.saturating_sub(sink.get_pos()) > self.crossfade
+        {
+            return Ok(None);
+        }
+
+        // Taken out first so starting the next track doesn't cut it off.
+        let outgoing = self.sink.take();
+        let song = self.current_song.clone();
+        if let Err(e) = self.advance(Some(self.crossfade)) {
+            self.sink = outgoing;
+            return Err(e);
+        }
+        if let Some(sink) = outgoing {
+            self.fade_out(sink, self.crossfade);
+        }
+        Ok(Some(song))
+    }
+
+    /// Sets how long the last track fades out before playback runs out of
+    /// tracks; zero disables the fade.
+    pub fn set_end_fade(&mut self, duration: Duration) {
+        self.end_fade = duration;
+    }
+
+    /// Once the current track is within the end fade of its end with nothing to
+    /// follow it (queue empty, no repeat), ramps it down to silence over the time
+    /// left; it still finishes as usual. Should something come to follow it, or
+    /// playback move back out of the window, the volume is restored. Returns
+    /// `true` when a fade started.
+    pub fn fade_at_end_if_due(&mut self) -> bool {
+        let (Some(sink), Some(duration)) = (&self.sink, self.current_duration) else {
+            return false;
+        };
+        if sink.empty() || sink.is_paused() {
+            return false;
+        }
+        let remaining = duration.saturating_sub(sink.get_pos());
+        let due = !self.end_fade.is_zero()
+            && remaining <= self.end_fade
+            && self.repeats_left == 0
+            && self.repeat_mode == RepeatMode::Off
+            && self.queue.is_empty();
+        if due == self.end_fading {
+            return false;
+        }
+
+        self.end_fading = due;
+        let sink = sink.clone();
+        if due {
+            self.ramp_sink(sink, 0.0, remaining, FadeCurve::Linear);
+        } else {
+            let volume = self.track_volume();
+            self.ramp_sink(sink, volume, self.volume_ramp, FadeCurve::Linear);
+        }
+        due
+    }
+
+    /// Starts writing the current track, from where it is now to its end, to a
+    /// WAV file at `path`. Replaces any recording already running. Returns the
+    /// path written to.
+    pub fn start_recording(&mut self, path: &str) -> SoundPlayerResult<PathBuf> {
         let sink = self.get_sink()?;
-        sink.stop();
-        self.sink = None;
+        let path = expand_tilde(path);
+        let recording_error = |source| SoundPlayerError::RecordingError {
+            file: path.display().to_string(),
+            source,
+        };
+        let format = self
+            .current_format
+            .ok_or_else(|| recording_error(io::Error::other("format of the track is unknown")))?;
+        let (recording, sender) = record_to_wav(&path, format).map_err(recording_error)?;
+        sink.record(Some(sender));
+        info!("Recording '{}' to {}", self.current_song, path.display());
+        if let Some(previous) = self.recording.replace(recording) {
+            previous.stop();
+        }
+        Ok(path)
+    }
+
+    /// Stops the running recording and finalizes its file. Returns its path, or
+    /// `None` if nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Option<PathBuf> {
+        let recording = self.recording.take()?;
+        if let Some(sink) = &self.sink {
+            sink.record(None);
+        }
+        let path = recording.path().to_path_buf();
+        recording.stop();
+        Some(path)
+    }
+
+    /// Recently played tracks, oldest first. Overlays are not recorded.
+    pub fn history(&self) -> Vec<String> {
+        self.history.iter().cloned().collect()
+    }
+
+    /// Plays the track that was playing before the current one. The current track
+    /// is put back at the front of the queue, so `play_next` returns to it.
+    /// Returns the name of the track now playing.
+    pub fn play_previous(&mut self) -> SoundPlayerResult<String> {
+        let current_is_last = self
+            .history
+            .back()
+            .is_some_and(|last| *last == self.current_song);
+        let index = self
+            .history
+            .len()
+            .checked_sub(1 + current_is_last as usize)
+            .ok_or(SoundPlayerError::HistoryEmpty)?;
+        let previous = self.history[index].clone();
+        let requeue =
+            (!self.current_song.is_empty() && !self.is_inline()).then(|| self.current_song.clone());
+
+        self.play(&previous, None)?;
+        // Step back rather than adding `previous` again on top of the current track.
+        self.history.truncate(index);
+        self.history.push_back(previous.clone());
+        if let Some(current) = requeue {
+            self.queue.push_front(current);
+        }
+        Ok(previous)
+    }
+
+    /// Stops the current track and plays the queue entry at `index` (zero-based).
+    /// The entries before it are dropped unless `keep` is set, in which case they
+    /// stay queued in order. With `RepeatMode::All` the current track goes to the
+    /// back of the queue, and so do dropped entries, as if `play_next` had been
+    /// called repeatedly. Returns the name of the track now playing.
+    pub fn jump_to(&mut self, index: usize, keep: bool) -> SoundPlayerResult<String> {
+        self.check_queue_index(index)?;
+        let skipped: Vec<String> = match keep {
+            true => Vec::new(),
+            false => self.queue.drain(..index).collect(),
+        };
+        let song_name = self
+            .queue
+            .remove(if keep { index } else { 0 })
+            .ok_or(SoundPlayerError::QueueEmpty)?;
+        if self.repeat_mode == RepeatMode::All {
+            if !self.current_song.is_empty() && !self.is_inline() {
+                self.queue.push_back(self.current_song.clone());
+            }
+            self.queue.extend(skipped);
+        }
+        self.play(&song_name, None)?;
+        Ok(song_name)
+    }
+
+    /// The next queued track, once the current one is within `PRELOAD_AHEAD` of
+    /// its end and the queue will advance to it. The caller is expected to
+    /// prepare it off the lock and hand the result to `finish_preload`. Each
+    /// track is offered only once, so a failed preload is not retried; the track
+    /// is then simply loaded when it's due.
+    pub fn preload_candidate(&mut self) -> Option<(String, TrackSource)> {
+        if self.repeat_mode == RepeatMode::One {
+            return None;
+        }
+        let next = self.queue.front()?;
+        if self
+            .preload
+            .as_ref()
+            .is_some_and(|preload| preload.song() == next)
+        {
+            return None;
+        }
+        let sink = self.sink.as_ref().filter(|sink| !sink.empty())?;
+        let remaining = self.current_duration?.saturating_sub(sink.get_pos());
+        if remaining > PRELOAD_AHEAD + self.crossfade {
+            return None;
+        }
+        let next = next.clone();
+        let source = self.resolve_source(&next).ok()?;
+        self.preload = Some(Preload::Pending(next.clone()));
+        Some((next, source))
+    }
+
+    /// Keeps the outcome of preparing `song_name`, unless the queue has moved on.
+    pub fn finish_preload(&mut self, song_name: String, result: SoundPlayerResult<B::Prepared>) {
+        if !matches!(&self.preload, Some(Preload::Pending(pending)) if *pending == song_name) {
+            return;
+        }
+        self.preload = Some(match result {
+            Ok(prepared) => Preload::Ready(song_name, prepared),
+            Err(e) => {
+                warn!(
+                    "Could not preload '{}', it will load on demand: {}",
+                    song_name, e
+                );
+                Preload::Failed(song_name)
+            }
+        });
+    }
+
+    /// Reads the entries of the M3U playlist at `path`, resolved like a song name.
+    pub fn load_m3u(&self, path: &str) -> SoundPlayerResult<Vec<String>> {
+        let path = self.resolve_path(path);
+        let file = path.display().to_string();
+        let mut text = String::new();
+        open_file(&path)?.read_to_string(&mut text).map_err(|e| {
+            SoundPlayerError::InvalidPlaylist {
+                file: file.clone(),
+                reason: e.to_string(),
+            }
+        })?;
+        let entries = parse_m3u(&text, path.parent().unwrap_or(Path::new("")));
+        if entries.is_empty() {
+            return Err(SoundPlayerError::InvalidPlaylist {
+                file,
+                reason: "no entries".to_string(),
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Replaces the queue with the playlist at `path` and starts its first entry.
+    /// Returns the number of tracks loaded.
+    pub fn load_playlist(&mut self, path: &str) -> SoundPlayerResult<usize> {
+        let mut entries: VecDeque<String> = self.load_m3u(path)?.into();
+        let count = entries.len();
+        let first = entries.pop_front().ok_or(SoundPlayerError::QueueEmpty)?;
+        self.queue = entries;
+        self.play(&first, None)?;
+        Ok(count)
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn check_queue_index(&self, index: usize) -> SoundPlayerResult<()> {
+        if index < self.queue.len() {
+            Ok(())
+        } else {
+            Err(SoundPlayerError::QueueIndexOutOfRange {
+                index,
+                len: self.queue.len(),
+            })
+        }
+    }
+
+    /// Removes the queue entry at `index` and returns the new queue length. The
+    /// current track is not part of the queue and can't be removed this way.
+    pub fn dequeue(&mut self, index: usize) -> SoundPlayerResult<usize> {
+        self.check_queue_index(index)?;
+        self.queue.remove(index);
+        Ok(self.queue.len())
+    }
+
+    /// Moves the queue entry at `from` so that it ends up at index `to`.
+    pub fn move_in_queue(&mut self, from: usize, to: usize) -> SoundPlayerResult<()> {
+        self.check_queue_index(from)?;
+        self.check_queue_index(to)?;
+        if let Some(entry) = self.queue.remove(from) {
+            self.queue.insert(to, entry);
+        }
+        Ok(())
+    }
+
+    /// Names of the queued tracks, next first. The current track is not included.
+    pub fn queue_contents(&self) -> Vec<String> {
+        self.queue.iter().cloned().collect()
+    }
+
+    /// Randomizes the order of the upcoming queue. The currently playing track and
+    /// anything already played are left untouched. A `seed` gives a deterministic order.
+    pub fn shuffle_queue(&mut self, seed: Option<u64>) {
+        let queue = self.queue.make_contiguous();
+        match seed {
+            Some(seed) => queue.shuffle(&mut StdRng::seed_from_u64(seed)),
+            None => queue.shuffle(&mut rand::rng()),
+        }
+    }
+
+    pub fn set_repeat_mode(&mut self, mode: RepeatMode) {
+        self.repeat_mode = mode;
+    }
+
+    /// Plays the current track `count` times in total, counting the play in
+    /// progress, before the queue advances or playback stops. Counts of 0 and 1
+    /// both mean it plays once.
+    pub fn set_repeat_count(&mut self, count: u32) -> SoundPlayerResult<()> {
+        self.get_sink()?;
+        self.repeats_left = count.saturating_sub(1);
+        Ok(())
+    }
+
+    /// Returns the name of the current track the first time it is seen to have
+    /// played to its end. Explicitly stopped tracks are never reported.
+    pub fn take_finished(&mut self) -> Option<String> {
+        let finished = self.sink.as_ref().is_some_and(|sink| sink.empty());
+        if !finished || self.finish_reported {
+            return None;
+        }
+        self.finish_reported = true;
+        Some(self.current_song.clone())
+    }
+
+    /// Once the current track has finished playing, replays it or starts the next
+    /// queued one according to the repeat mode. Returns `true` if playback advanced.
+    pub fn advance_if_finished(&mut self) -> SoundPlayerResult<bool> {
+        let finished = self.sink.as_ref().is_some_and(|sink| sink.empty());
+        if !finished {
+            return Ok(false);
+        }
+        if self.repeats_left > 0 {
+            self.repeats_left -= 1;
+            self.replay()?;
+            return Ok(true);
+        }
+        match self.repeat_mode {
+            RepeatMode::One => {
+                self.replay()?;
+            }
+            _ if !self.can_advance() => return Ok(false),
+            RepeatMode::All | RepeatMode::Off => self.play_next()?,
+        }
+        Ok(true)
+    }
+
+    /// Ramps the volume down to silence over `duration` on a background thread, then
+    /// stops. The player is considered stopped immediately; a subsequent `play`
+    /// cancels the fade and cuts the old track off.
+    pub fn stop_with_fade(&mut self, duration: Duration) -> SoundPlayerResult<()> {
+        self.load_generation += 1;
+        let sink = self.sink.take().ok_or(SoundPlayerError::NoSongLoaded)?;
         self.current_song.clear();
+        self.current_duration = None;
+        self.current_format = None;
+
+        self.cancel_fade_out();
+        self.cancel_volume_ramp();
+        self.fade_out(sink, duration);
+        Ok(())
+    }
+
+    /// Ramps `sink` down to silence over `duration` on a background thread, then
+    /// stops it. Cancelling the fade stops it at once.
+    fn fade_out(&mut self, sink: Arc<B::Sink>, duration: Duration) {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.fade_out_cancel = Some(cancelled.clone());
+
+        thread::spawn(move || {
+            let start_volume = sink.volume();
+            let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+            for step in 1..=steps {
+                if cancelled.load(Ordering::SeqCst) {
+                    break;
+                }
+                sink.set_volume(start_volume * (1.0 - step as f32 / steps as f32));
+                thread::sleep(FADE_STEP);
+            }
+            sink.stop();
+            // Tells `stop` the fade is over, however it ended.
+            cancelled.store(true, Ordering::SeqCst);
+        });
+    }
+
+    /// Loops playback between `start` and `end` (in seconds) of the current track
+    /// until cleared or another song is played. When the track's length is known
+    /// the region must end within it.
+    pub fn set_ab_loop(&mut self, start: f64, end: f64) -> SoundPlayerResult<()> {
+        self.get_sink()?;
+        if let Some(duration) = self.current_duration
+            && end > duration.as_secs_f64()
+        {
+            return Err(SoundPlayerError::SeekOutOfRange {
+                position: end,
+                duration: duration.as_secs_f64(),
+            });
+        }
+        self.ab_loop = Some((to_duration(start)?, to_duration(end)?));
         Ok(())
     }
 
-    pub fn seek(&self, position: u64) -> SoundPlayerResult<()> {
+    pub fn clear_ab_loop(&mut self) {
+        self.ab_loop = None;
+    }
+
+    /// Seeks back to the start of the A-B region once playback has reached its
+    /// end. Returns `true` if it did.
+    pub fn enforce_ab_loop(&self) -> SoundPlayerResult<bool> {
+        let (Some((start, end)), Some(sink)) = (self.ab_loop, self.sink.as_ref()) else {
+            return Ok(false);
+        };
+        if sink.empty() || sink.get_pos() < end {
+            return Ok(false);
+        }
+        sink.try_seek(start)
+            .map_err(|e| SoundPlayerError::SeekError {
+                position: start.as_secs_f64(),
+                source: e,
+            })?;
+        Ok(true)
+    }
+
+    fn cancel_fade_out(&mut self) {
+        if let Some(cancelled) = self.fade_out_cancel.take() {
+            cancelled.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn cancel_volume_ramp(&mut self) {
+        if let Some(cancelled) = self.volume_ramp_cancel.take() {
+            cancelled.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Seeks to `position` seconds. Positions past the end of a track of known
+    /// length are rejected; the end itself is allowed.
+    pub fn seek(&self, position: f64) -> SoundPlayerResult<()> {
+        let sink = self.get_sink()?;
+        if let Some(duration) = self.current_duration
+            && position > duration.as_secs_f64()
+        {
+            return Err(SoundPlayerError::SeekOutOfRange {
+                position,
+                duration: duration.as_secs_f64(),
+            });
+        }
+        let target = to_duration(position)?;
+        sink.try_seek(target)
+            .map_err(|e| SoundPlayerError::SeekError {
+                position,
+                source: e,
+            })?;
+        Ok(())
+    }
+
+    /// Jumps to `percent` (0 to 100) of the way through the current track, going
+    /// by the length read when it was loaded.
+    pub fn seek_percent(&self, percent: f64) -> SoundPlayerResult<()> {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(SoundPlayerError::InvalidSeekPercent { percent });
+        }
+        self.get_sink()?;
+        let duration = self
+            .current_duration
+            .ok_or(SoundPlayerError::DurationUnknown)?;
+        self.seek(duration.as_secs_f64() * percent / 100.0)
+    }
+
+    /// Jumps to `offset_secs` before the end of the current track, going by the
+    /// length read when it was loaded; offsets longer than the track go to its
+    /// start. Returns the position jumped to.
+    pub fn seek_end(&self, offset_secs: f64) -> SoundPlayerResult<f64> {
+        self.get_sink()?;
+        let duration = self
+            .current_duration
+            .ok_or(SoundPlayerError::DurationUnknown)?;
+        let position = (duration.as_secs_f64() - offset_secs).max(0.0);
+        self.seek(position)?;
+        Ok(position)
+    }
+
+    /// Jumps back to the start of the current track without re-decoding it. Falls
+    /// back to replaying the file when the source can't seek or has already ended.
+    pub fn restart(&mut self) -> SoundPlayerResult<()> {
+        let sink = self.get_sink()?;
+        if !sink.empty() {
+            match sink.try_seek(Duration::ZERO) {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!("Seek to start failed, replaying instead: {}", e),
+            }
+        }
+        self.replay()
+    }
+
+    /// Seeks relative to the current position, clamping at the start of the track
+    /// and, when its length is known, at its end.
+    pub fn seek_relative(&self, delta_secs: f64) -> SoundPlayerResult<()> {
         let sink = self.get_sink()?;
-        sink.try_seek(Duration::from_secs(position))
+        let mut position = (sink.get_pos().as_secs_f64() + delta_secs).max(0.0);
+        if let Some(duration) = self.current_duration {
+            position = position.min(duration.as_secs_f64());
+        }
+        sink.try_seek(to_duration(position)?)
             .map_err(|e| SoundPlayerError::SeekError {
                 position,
                 source: e,
@@ -134,21 +1833,391 @@ impl SoundPlayer {
         Ok(())
     }
 
-    pub fn volume(&self, volume: f32) -> SoundPlayerResult<()> {
-        if !(0.0..=1.0).contains(&volume) {
+    /// Sets the volume, gliding to it over the configured ramp time so the jump
+    /// doesn't click. With no track loaded the level is kept for the next one.
+    pub fn volume(&mut self, volume: f32) -> SoundPlayerResult<()> {
+        self.ramp_volume(volume, self.volume_ramp)
+    }
+
+    /// Glides the volume from its current level to `volume` over `duration` on a
+    /// background thread. A later volume change, mute or track change cancels the
+    /// ramp; the next one starts from wherever it got to.
+    pub fn ramp_volume(&mut self, volume: f32, duration: Duration) -> SoundPlayerResult<()> {
+        self.fade_volume(volume, duration, FadeCurve::Linear)
+    }
+
+    /// Like `ramp_volume`, with the level following `curve` over time.
+    pub fn fade_volume(
+        &mut self,
+        volume: f32,
+        duration: Duration,
+        curve: FadeCurve,
+    ) -> SoundPlayerResult<()> {
+        if !(0.0..=MAX_VOLUME).contains(&volume) {
+            return Err(SoundPlayerError::InvalidVolume { volume });
+        }
+        self.last_volume = volume;
+        if self.muted_volume.is_some() {
+            // Stay muted; unmuting picks up the new level.
+            self.muted_volume = Some(volume);
+        }
+        if let Some(sink) = self.sink.clone() {
+            self.ramp_sink(sink, self.track_volume(), duration, curve);
+        }
+        Ok(())
+    }
+
+    /// Sets the master volume, which scales every track's volume and survives
+    /// track changes. Applies to the current track (ramped like `volume`) and to
+    /// overlays, and needs no track to be loaded.
+    pub fn set_master_volume(&mut self, volume: f32) -> SoundPlayerResult<()> {
+        if !(0.0..=MAX_VOLUME).contains(&volume) {
             return Err(SoundPlayerError::InvalidVolume { volume });
         }
+        self.master_volume = volume;
+        if let Some(sink) = self.sink.clone() {
+            self.ramp_sink(
+                sink,
+                self.track_volume(),
+                self.volume_ramp,
+                FadeCurve::Linear,
+            );
+        }
+        for overlay in &self.overlays {
+            overlay.set_volume(self.output_volume());
+        }
+        Ok(())
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    /// Moves `sink` to `volume` over `duration` along `curve`, cancelling any
+    /// earlier ramp.
+    fn ramp_sink(&mut self, sink: Arc<B::Sink>, volume: f32, duration: Duration, curve: FadeCurve) {
+        self.cancel_volume_ramp();
+        if duration < RAMP_STEP {
+            sink.set_volume(volume);
+            return;
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.volume_ramp_cancel = Some(cancelled.clone());
+        thread::spawn(move || {
+            let start_volume = sink.volume();
+            let steps = (duration.as_millis() / RAMP_STEP.as_millis()).max(1) as u32;
+            for step in 1..=steps {
+                thread::sleep(RAMP_STEP);
+                if cancelled.load(Ordering::SeqCst) {
+                    return;
+                }
+                let progress = curve.ease(step as f32 / steps as f32);
+                sink.set_volume(start_volume + (volume - start_volume) * progress);
+            }
+        });
+    }
+
+    /// Like `volume`, but pulls out-of-range levels into range instead of
+    /// rejecting them. Returns the level applied; NaN is still an error.
+    pub fn volume_clamped(&mut self, volume: f32) -> SoundPlayerResult<f32> {
+        let volume = volume.clamp(0.0, MAX_VOLUME);
+        self.volume(volume)?;
+        Ok(volume)
+    }
+
+    /// Sets the volume from a decibel value (0 dB is unity gain) and returns the
+    /// resulting linear gain. Gains above `MAX_VOLUME` are rejected.
+    pub fn set_volume_db(&mut self, db: f32) -> SoundPlayerResult<f32> {
+        let gain = 10f32.powf(db / 20.0);
+        self.volume(gain)?;
+        Ok(gain)
+    }
+
+    /// Sets the playback rate, pitch included. Speeds must be above zero and no
+    /// more than the configured maximum. Like the volume, it can be set before a
+    /// track is loaded and carries over to later tracks.
+    pub fn speed(&mut self, speed: f32) -> SoundPlayerResult<()> {
+        if !(speed > 0.0 && speed <= self.max_speed) {
+            return Err(SoundPlayerError::InvalidSpeed {
+                speed,
+                max: self.max_speed,
+            });
+        }
+        if let Some(sink) = &self.sink {
+            sink.set_speed(speed);
+        }
+        self.last_speed = speed;
+        Ok(())
+    }
+
+    /// Like `speed`, but pulls speeds below `MIN_SPEED` or above the maximum into
+    /// range instead of rejecting them. Returns the speed applied.
+    pub fn speed_clamped(&mut self, speed: f32) -> SoundPlayerResult<f32> {
+        let speed = speed.clamp(MIN_SPEED.min(self.max_speed), self.max_speed);
+        self.speed(speed)?;
+        Ok(speed)
+    }
+
+    /// Changes the playback rate without shifting pitch. Unlike `speed` this
+    /// time-stretches the decoded audio, which costs noticeably more CPU for as
+    /// long as the tempo is not 1.0.
+    pub fn tempo(&mut self, tempo: f32) -> SoundPlayerResult<()> {
+        if !(MIN_TEMPO..=MAX_TEMPO).contains(&tempo) {
+            return Err(SoundPlayerError::InvalidTempo { tempo });
+        }
         let sink = self.get_sink()?;
-        sink.set_volume(volume);
+        sink.set_tempo(tempo);
+        self.last_tempo = tempo;
         Ok(())
     }
 
-    pub fn speed(&self, speed: f32) -> SoundPlayerResult<()> {
-        if speed <= 0.0 {
-            return Err(SoundPlayerError::InvalidSpeed { speed });
+    /// Shifts the stereo image: -1.0 plays only the left channel, 1.0 only the
+    /// right. Kept for later tracks, like the volume.
+    pub fn balance(&mut self, pan: f32) -> SoundPlayerResult<()> {
+        if !(-1.0..=1.0).contains(&pan) {
+            return Err(SoundPlayerError::InvalidBalance { pan });
+        }
+        let sink = self.get_sink()?;
+        sink.set_pan(pan);
+        self.last_pan = pan;
+        Ok(())
+    }
+
+    /// Plays every channel's average on all of them, for the current track (if
+    /// any), overlays and later tracks; off leaves the channels as they are.
+    pub fn set_mono(&mut self, enabled: bool) {
+        self.mono = enabled;
+        for sink in self.sink.iter().map(|sink| &**sink).chain(&self.overlays) {
+            sink.set_mono(enabled);
+        }
+    }
+
+    /// Sets the gain in dB of each equalizer band (see `EQ_BANDS_HZ`). The profile
+    /// stays in effect for later tracks; all zeros switches the equalizer off.
+    pub fn set_equalizer(&mut self, gains: EqualizerGains) -> SoundPlayerResult<()> {
+        if let Some(&gain) = gains.iter().find(|gain| gain.abs() > MAX_EQ_GAIN_DB) {
+            return Err(SoundPlayerError::InvalidEqualizerGain { gain });
         }
         let sink = self.get_sink()?;
-        sink.set_speed(speed);
+        sink.set_equalizer(gains);
+        self.equalizer = gains;
+        Ok(())
+    }
+
+    pub fn mute(&mut self) -> SoundPlayerResult<()> {
+        let sink = self.sink.clone().ok_or(SoundPlayerError::NoSongLoaded)?;
+        self.cancel_volume_ramp();
+        if self.muted_volume.is_none() {
+            self.muted_volume = Some(self.last_volume);
+            sink.set_volume(0.0);
+        }
+        Ok(())
+    }
+
+    pub fn unmute(&mut self) -> SoundPlayerResult<()> {
+        let sink = self.sink.clone().ok_or(SoundPlayerError::NoSongLoaded)?;
+        self.cancel_volume_ramp();
+        if let Some(volume) = self.muted_volume.take() {
+            self.last_volume = volume;
+        }
+        sink.set_volume(self.track_volume());
+        Ok(())
+    }
+
+    /// Current playback position; frozen while the sink is paused.
+    pub fn position(&self) -> SoundPlayerResult<Duration> {
+        let sink = self.get_sink()?;
+        Ok(sink.get_pos())
+    }
+
+    /// Sample rate, channel count and codec of the loaded track. The codec is read
+    /// from the container headers on request; it's `None` for network streams.
+    pub fn audio_info(&self) -> SoundPlayerResult<AudioInfo> {
+        self.get_sink()?;
+        let info = match &self.current_source {
+            Some(TrackSource::File(path) | TrackSource::Reversed(path)) => {
+                read_stream_info(path).ok()
+            }
+            Some(TrackSource::Inline { data, format }) => {
+                read_inline_stream_info(data, format).ok()
+            }
+            Some(TrackSource::Url(_)) | None => None,
+        };
+        Ok(AudioInfo {
+            sample_rate: self.current_format.map(|format| format.sample_rate),
+            channels: self.current_format.map(|format| format.channels),
+            codec: info.and_then(|info| info.codec),
+        })
+    }
+
+    /// Total length of the loaded track, or `None` when the format doesn't report it.
+    pub fn duration(&self) -> SoundPlayerResult<Option<Duration>> {
+        self.get_sink()?;
+        Ok(self.current_duration)
+    }
+
+    /// Elapsed and remaining time of the loaded track, with its length.
+    pub fn time_info(&self) -> SoundPlayerResult<TimeInfo> {
+        let elapsed = self.position()?;
+        Ok(TimeInfo {
+            elapsed_secs: elapsed.as_secs_f64(),
+            remaining_secs: self
+                .current_duration
+                .map(|duration| duration.saturating_sub(elapsed).as_secs_f64()),
+            duration_secs: self.current_duration.map(|d| d.as_secs_f64()),
+        })
+    }
+
+    /// Whether the loaded track can seek anywhere; streams can only skip ahead.
+    pub fn can_seek(&self) -> SoundPlayerResult<bool> {
+        self.get_sink()?;
+        Ok(self.current_seekable)
+    }
+
+    /// Gathers the full player state in one pass. Never fails: fields that need a
+    /// loaded song are `None`/`false` when nothing is loaded.
+    pub fn snapshot(&self) -> PlayerSnapshot {
+        let sink = self.sink.as_ref();
+        PlayerSnapshot {
+            current_song: sink.map(|_| self.current_song.clone()),
+            is_playing: sink.is_some_and(|sink| !sink.empty() && !sink.is_paused()),
+            is_paused: sink.is_some_and(|sink| sink.is_paused()),
+            volume: sink.map(|_| match self.muted_volume {
+                Some(_) => 0.0,
+                None => self.last_volume,
+            }),
+            master_volume: self.master_volume,
+            position_secs: sink.map(|sink| sink.get_pos().as_secs_f64()),
+            duration_secs: sink.and(self.current_duration).map(|d| d.as_secs_f64()),
+            repeat_mode: self.repeat_mode,
+            repeats_remaining: sink.map(|_| self.repeats_left),
+        }
+    }
+
+    /// Starts or stops measuring the levels of the current track and the ones
+    /// after it. Overlays aren't metered.
+    pub fn set_metering(&mut self, enabled: bool) {
+        self.metering = enabled;
+        if let Some(sink) = &self.sink {
+            sink.set_metering(enabled);
+        }
+    }
+
+    /// Levels of the current track since the last call, while metering.
+    pub fn take_levels(&self) -> Option<Levels> {
+        self.sink.as_ref().and_then(|sink| sink.take_levels())
+    }
+
+    /// Tags of the loaded track. Network streams are not probed for them.
+    pub fn metadata(&self) -> SoundPlayerResult<TrackMetadata> {
+        self.get_sink()?;
+        let metadata = match &self.current_source {
+            Some(TrackSource::File(path) | TrackSource::Reversed(path)) => read_metadata(path),
+            Some(TrackSource::Inline { data, format }) => read_inline_metadata(data, format),
+            Some(TrackSource::Url(_)) | None => {
+                return Err(SoundPlayerError::MetadataUnavailable {
+                    song: self.current_song.clone(),
+                });
+            }
+        };
+        metadata.map_err(|e| SoundPlayerError::MetadataError {
+            file: self.current_song.clone(),
+            source: e,
+        })
+    }
+
+    /// Whether the current track was sent inline rather than named.
+    fn is_inline(&self) -> bool {
+        matches!(self.current_source, Some(TrackSource::Inline { .. }))
+    }
+
+    /// Whether advancing puts the current track back at the end of the queue, as
+    /// `RepeatMode::All` does with every track that can be played again by name.
+    fn requeues_current(&self) -> bool {
+        self.repeat_mode == RepeatMode::All && !self.current_song.is_empty() && !self.is_inline()
+    }
+
+    /// Whether `play_next` has a track to move on to.
+    fn can_advance(&self) -> bool {
+        !self.queue.is_empty() || self.requeues_current()
+    }
+
+    /// The file configured for automatic session saves, if any.
+    pub fn state_file(&self) -> Option<&Path> {
+        self.state_file.as_deref()
+    }
+
+    pub fn session_state(&self) -> SessionState {
+        let sink = self.sink.as_ref().filter(|sink| !sink.empty());
+        SessionState {
+            // Inline audio isn't saved, so there is nothing to restore it from.
+            song: sink
+                .filter(|_| !self.is_inline())
+                .map(|_| self.current_song.clone()),
+            position_secs: sink.map_or(0.0, |sink| sink.get_pos().as_secs_f64()),
+            paused: sink.is_some_and(|sink| sink.is_paused()),
+            volume: self.last_volume,
+            master_volume: self.master_volume,
+            speed: self.last_speed,
+            queue: self.queue.iter().cloned().collect(),
+        }
+    }
+
+    /// Writes the session to `path` as JSON. The file is replaced atomically, so
+    /// a crash mid-write leaves the previous save intact.
+    pub fn save_state(&self, path: &Path) -> SoundPlayerResult<()> {
+        let state_error = |source| SoundPlayerError::StateFileError {
+            file: path.display().to_string(),
+            source,
+        };
+        let json = serde_json::to_string_pretty(&self.session_state())
+            .map_err(|e| state_error(e.into()))?;
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, json).map_err(state_error)?;
+        fs::rename(&temp, path).map_err(state_error)
+    }
+
+    /// Restores a session written by `save_state`: volume, speed and queue are
+    /// applied, then the saved song is replayed from its saved position.
+    pub fn load_state(&mut self, path: &Path) -> SoundPlayerResult<()> {
+        let file = path.display().to_string();
+        let json = fs::read_to_string(path).map_err(|source| SoundPlayerError::StateFileError {
+            file: file.clone(),
+            source,
+        })?;
+        let state: SessionState = serde_json::from_str(&json)
+            .map_err(|source| SoundPlayerError::InvalidStateFile { file, source })?;
+
+        if (0.0..=MAX_VOLUME).contains(&state.volume) {
+            self.last_volume = state.volume;
+        } else {
+            warn!("Ignoring saved volume {}", state.volume);
+        }
+        if (0.0..=MAX_VOLUME).contains(&state.master_volume) {
+            self.master_volume = state.master_volume;
+        } else {
+            warn!("Ignoring saved master volume {}", state.master_volume);
+        }
+        if state.speed > 0.0 && state.speed <= self.max_speed {
+            self.last_speed = state.speed;
+        } else {
+            warn!("Ignoring saved speed {}", state.speed);
+        }
+        self.queue = state.queue.into();
+
+        if let Some(song) = state.song {
+            self.play(&song, None)?;
+            if state.position_secs > 0.0
+                && let Err(e) = self.seek(state.position_secs)
+            {
+                warn!("Could not restore saved position: {}", e);
+            }
+            if state.paused {
+                self.pause()?;
+            }
+        }
         Ok(())
     }
 
@@ -171,8 +2240,79 @@ impl SoundPlayer {
         Ok(sink.empty())
     }
 
+    /// Level the sink is actually playing at, with the master volume and mute
+    /// applied.
     pub fn get_volume(&self) -> SoundPlayerResult<f32> {
         let sink = self.get_sink()?;
         Ok(sink.volume())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_backend::MockBackend;
+
+    fn mock_player() -> SoundPlayer<MockBackend> {
+        let options = PlayerOptions {
+            base_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("songs"),
+            ..PlayerOptions::default()
+        };
+        SoundPlayer::new(MockBackend::new(), options)
+    }
+
+    /// Ends the current track as if it had played to the end.
+    fn finish(player: &SoundPlayer<MockBackend>) {
+        player.sink.as_ref().unwrap().stop();
+    }
+
+    #[test]
+    fn stop_cuts_a_fade_out_short() {
+        let mut player = mock_player();
+        player.play("cone.mp3", None).unwrap();
+        player.stop_with_fade(Duration::from_secs(10)).unwrap();
+        assert!(player.stop().is_ok());
+        // Nothing is left to stop now.
+        assert!(matches!(player.stop(), Err(SoundPlayerError::NoSongLoaded)));
+    }
+
+    #[test]
+    fn stop_abandons_a_background_load() {
+        let mut player = mock_player();
+        player.background_load_bytes = 1;
+        let (source, generation) = player.begin_background_load("cone.mp3").unwrap().unwrap();
+        assert!(player.stop().is_ok());
+        let started = player
+            .finish_background_load("cone.mp3", source.clone(), generation, Ok(source), None)
+            .unwrap();
+        assert!(!started);
+    }
+
+    #[test]
+    fn stop_without_a_track_changes_nothing() {
+        let mut player = mock_player();
+        assert!(matches!(player.stop(), Err(SoundPlayerError::NoSongLoaded)));
+        assert_eq!(player.load_generation, 0);
+    }
+
+    #[test]
+    fn repeat_all_replays_a_named_track_once_the_queue_runs_out() {
+        let mut player = mock_player();
+        player.set_repeat_mode(RepeatMode::All);
+        player.play("cone.mp3", None).unwrap();
+        finish(&player);
+        assert!(player.advance_if_finished().unwrap());
+        assert_eq!(player.current_song(), "cone.mp3");
+    }
+
+    #[test]
+    fn repeat_all_stops_after_an_inline_track_once_the_queue_runs_out() {
+        let mut player = mock_player();
+        player.set_repeat_mode(RepeatMode::All);
+        player.play_inline("UklGRg==", "wav").unwrap();
+        finish(&player);
+        // Inline tracks aren't re-queued, so there's nothing to advance to.
+        assert!(!player.advance_if_finished().unwrap());
+        assert!(!player.advance_if_finished().unwrap());
+    }
+}
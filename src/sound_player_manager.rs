@@ -1,12 +1,80 @@
 use crate::{
+    backend::{AudioBackend, RodioBackend, TrackSource},
     command::*,
-    order::{self, Order},
+    event::PlayerEvent,
+    meter::DEFAULT_LEVELS_INTERVAL,
+    metrics::Metrics,
+    order::Order,
+    playlist::is_playlist,
+    response::{CommandResponse, Reply},
     sound_player::*,
 };
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError, Weak,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+fn lock<B: AudioBackend>(sound_player: &Mutex<SoundPlayer<B>>) -> MutexGuard<'_, SoundPlayer<B>> {
+    sound_player.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// How often the background watcher checks whether the current track has finished.
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the session is saved to the configured state file.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
 
-pub struct SoundPlayerManager {
-    sound_player: SoundPlayer,
+/// The state file an order refers to: its own path, or else the configured one.
+fn state_path<B: AudioBackend>(
+    player: &SoundPlayer<B>,
+    path: Option<String>,
+) -> SoundPlayerResult<PathBuf> {
+    path.map(|path| expand_tilde(&path))
+        .or_else(|| player.state_file().map(Path::to_path_buf))
+        .ok_or(SoundPlayerError::NoStateFile)
+}
+
+/// A track `play` opens in the background, and how to start it.
+struct BackgroundLoad {
+    song: String,
+    source: TrackSource,
+    generation: u64,
+    fade_in: Option<Duration>,
+}
+
+/// Fade-out applied when the sleep timer stops playback.
+const SLEEP_FADE: Duration = Duration::from_secs(10);
+
+/// Player that orders without a `player_id` are addressed to.
+pub const DEFAULT_PLAYER_ID: &str = "default";
+
+/// Most players a manager holds, the default one included.
+pub const MAX_PLAYERS: usize = 16;
+
+pub struct SoundPlayerManager<B: AudioBackend = RodioBackend> {
+    /// Players by id. The default one exists from the start; others are created
+    /// the first time an order names them.
+    players: HashMap<String, Arc<Mutex<SoundPlayer<B>>>>,
+    /// What players created later are configured with.
+    options: PlayerOptions,
+    events: Receiver<PlayerEvent>,
+    event_sender: Sender<PlayerEvent>,
+    /// Whether a snapshot is pushed after every state-changing order.
+    subscribed: bool,
+    started_at: Instant,
+    metrics: Metrics,
+    /// Pending sleep timers by player id; dropping one cancels it.
+    sleep_timers: HashMap<String, Sender<()>>,
+    /// Threads sending levels events, by player id; dropping one stops it.
+    level_meters: HashMap<String, Sender<()>>,
 }
 
 #[derive(Debug)]
@@ -15,100 +83,828 @@ pub enum SoundPlayerManagerError {
 }
 
 impl SoundPlayerManager {
-    pub fn new() -> Result<Self, SoundPlayerManagerError> {
-        let sound_player = match SoundPlayer::new() {
-            Ok(sp) => sp,
+    pub fn new(options: PlayerOptions) -> Result<Self, SoundPlayerManagerError> {
+        match RodioBackend::new() {
+            Ok(backend) => Ok(Self::with_backend(backend, options)),
             Err(e) => {
                 error!("Failed to initialize SoundPlayer: {}", e);
-                return Err(SoundPlayerManagerError::InitFail);
+                Err(SoundPlayerManagerError::InitFail)
             }
-        };
-        Ok(Self { sound_player })
+        }
     }
+}
 
-    pub fn execute_command(&mut self, command: Command) -> SoundPlayerResult<()> {
-        match command {
-            Command::Play { song_name } => self.sound_player.play(&song_name)?,
-            Command::Stop => self.sound_player.stop()?,
-            Command::Pause => self.sound_player.pause()?,
-            Command::Resume => self.sound_player.resume()?,
-            Command::Seek { position } => self.sound_player.seek(position)?,
-            Command::Volume { level } => self.sound_player.volume(level)?,
-            Command::Speed { factor } => self.sound_player.speed(factor)?,
+impl<B: AudioBackend> Drop for SoundPlayerManager<B> {
+    fn drop(&mut self) {
+        // Save once more so a clean shutdown loses nothing since the last auto-save.
+        {
+            let player = lock(self.default_player());
+            if let Some(path) = player.state_file()
+                && let Err(e) = player.save_state(path)
+            {
+                error!("Failed to save session state: {}", e);
+            }
+        }
+        // However serving ended, nothing is left sounding.
+        for player in self.players.values() {
+            lock(player).stop_everything();
+        }
+    }
+}
+
+impl<B: AudioBackend> SoundPlayerManager<B> {
+    /// Creates a manager around `backend`. If a state file is configured and
+    /// exists, the session saved in it is restored.
+    pub fn with_backend(backend: B, options: PlayerOptions) -> Self {
+        let mut player = SoundPlayer::new(backend, options.clone());
+        if let Some(path) = player.state_file().map(Path::to_path_buf)
+            && path.exists()
+        {
+            match player.load_state(&path) {
+                Ok(()) => info!("Restored session state from {}", path.display()),
+                Err(e) => warn!("Could not restore session state: {}", e),
+            }
+        }
+        let sound_player = Arc::new(Mutex::new(player));
+        let (sender, events) = mpsc::channel();
+        Self::spawn_watcher(Arc::downgrade(&sound_player), None, sender.clone());
+        Self {
+            players: HashMap::from([(DEFAULT_PLAYER_ID.to_string(), sound_player)]),
+            options,
+            events,
+            event_sender: sender,
+            subscribed: false,
+            started_at: Instant::now(),
+            metrics: Metrics::default(),
+            sleep_timers: HashMap::new(),
+            level_meters: HashMap::new(),
         }
-        Ok(())
     }
 
-    pub fn process_order(&mut self, order: Order) -> String {
-        let cmd = match Command::try_from(&order) {
-            Ok(c) => c,
-            Err(e) => match e {
-                CommandParseError::InvalidParameters => {
-                    error!(
-                        "Invalid parameters in command: '{}'",
-                        order.parameters.join(" ")
-                    );
-                    return format!(
-                        "Invalid parameters in command: '{}'",
-                        order.parameters.join(" ")
+    fn default_player(&self) -> &Arc<Mutex<SoundPlayer<B>>> {
+        &self.players[DEFAULT_PLAYER_ID]
+    }
+
+    /// The player called `id`, created on the default player's output device if
+    /// it doesn't exist yet.
+    fn player(&mut self, id: &str) -> SoundPlayerResult<Arc<Mutex<SoundPlayer<B>>>> {
+        if let Some(player) = self.players.get(id) {
+            return Ok(player.clone());
+        }
+        if self.players.len() >= MAX_PLAYERS {
+            return Err(SoundPlayerError::TooManyPlayers { limit: MAX_PLAYERS });
+        }
+        let backend = lock(self.default_player()).fork_backend()?;
+        // The state file belongs to the default player; others would overwrite it.
+        // The track gains are shared with it instead of loaded again.
+        let options = PlayerOptions {
+            state_file: None,
+            track_gains_file: None,
+            ..self.options.clone()
+        };
+        let mut player = SoundPlayer::new(backend, options);
+        player.share_track_gains(&lock(self.default_player()));
+        let player = Arc::new(Mutex::new(player));
+        Self::spawn_watcher(
+            Arc::downgrade(&player),
+            Some(id.to_string()),
+            self.event_sender.clone(),
+        );
+        info!("Created player '{}'", id);
+        self.players.insert(id.to_string(), player.clone());
+        Ok(player)
+    }
+
+    /// Polls the player, reports tracks that ended, advances the queue, drops
+    /// finished overlays and periodically saves the session. The thread exits once the manager (and
+    /// with it the last strong reference) is dropped. Events carry `player_id`.
+    fn spawn_watcher(
+        sound_player: Weak<Mutex<SoundPlayer<B>>>,
+        player_id: Option<String>,
+        events: Sender<PlayerEvent>,
+    ) {
+        thread::spawn(move || {
+            let mut last_save = Instant::now();
+            while let Some(sound_player) = sound_player.upgrade() {
+                let mut player = lock(&sound_player);
+                if last_save.elapsed() >= AUTOSAVE_INTERVAL {
+                    last_save = Instant::now();
+                    if let Some(path) = player.state_file()
+                        && let Err(e) = player.save_state(path)
+                    {
+                        error!("Failed to auto-save session state: {}", e);
+                    }
+                }
+                let reaped = player.reap_overlays();
+                if reaped > 0 {
+                    debug!("Dropped {} finished overlays", reaped);
+                }
+                if let Err(e) = player.enforce_ab_loop() {
+                    error!("Failed to loop A-B region: {}", e);
+                }
+                match player.crossfade_if_due() {
+                    Ok(Some(song)) => {
+                        info!("Crossfading from '{}' to '{}'", song, player.current_song());
+                        let _ = events.send(PlayerEvent::TrackFinished {
+                            song,
+                            player_id: player_id.clone(),
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to crossfade to the next track: {}", e),
+                }
+                if player.fade_at_end_if_due() {
+                    debug!(
+                        "Fading out '{}' at the end of the queue",
+                        player.current_song()
                     );
                 }
-                CommandParseError::UnknownCommand => {
-                    error!("Unknown command: '{}'", order.command_name);
-                    return format!("Unknown command: '{}'", order.command_name);
+                if let Some(song) = player.take_finished() {
+                    info!("Finished playing: '{}'", song);
+                    let _ = events.send(PlayerEvent::TrackFinished {
+                        song,
+                        player_id: player_id.clone(),
+                    });
                 }
-            },
-        };
-
-        if let Err(e) = self.execute_command(cmd) {
-            match e {
-                SoundPlayerError::PlayError { file, source } => {
-                    error!("Failed to play '{}': {}", file, source);
-                    return format!("Failed to play '{}': {}", file, source);
+                match player.advance_if_finished() {
+                    Ok(true) => info!("Now playing: '{}'", player.current_song()),
+                    Ok(false) => {}
+                    Err(e) => error!("Failed to advance queue: {}", e),
                 }
-                SoundPlayerError::SeekError { position, source } => {
-                    error!("Failed to seek to {}: {}", position, source);
-                    return format!("Failed to seek to {}: {}", position, source);
+                let preload = player.preload_candidate();
+                drop(player);
+                if let Some((song, source)) = preload {
+                    Self::spawn_preload(Arc::downgrade(&sound_player), song, source);
                 }
-                SoundPlayerError::InvalidVolume { volume } => {
-                    warn!("Invalid volume: {}", volume);
-                    return format!("Invalid volume: {}", volume);
+                drop(sound_player);
+                thread::sleep(WATCH_INTERVAL);
+            }
+        });
+    }
+
+    /// Prepares `source` on its own thread, so slow opens (large files, network
+    /// streams) hold up neither the watcher nor the player.
+    fn spawn_preload(sound_player: Weak<Mutex<SoundPlayer<B>>>, song: String, source: TrackSource) {
+        thread::spawn(move || {
+            debug!("Preloading '{}'", song);
+            let prepared = B::prepare(&source);
+            if let Some(sound_player) = sound_player.upgrade() {
+                lock(&sound_player).finish_preload(song, prepared);
+            }
+        });
+    }
+
+    /// Prepares a large track given to `play` on its own thread and starts it
+    /// once it's ready, unless something else was played or playback stopped in
+    /// between. Reports `ready`, or `load_failed` if it won't play.
+    fn spawn_background_load(
+        sound_player: Weak<Mutex<SoundPlayer<B>>>,
+        load: BackgroundLoad,
+        player_id: Option<String>,
+        events: Sender<PlayerEvent>,
+    ) {
+        thread::spawn(move || {
+            debug!("Opening '{}' in the background", load.song);
+            let prepared = B::prepare(&load.source);
+            let Some(sound_player) = sound_player.upgrade() else {
+                return;
+            };
+            let result = lock(&sound_player).finish_background_load(
+                &load.song,
+                load.source,
+                load.generation,
+                prepared,
+                load.fade_in,
+            );
+            let song = load.song;
+            match result {
+                Ok(true) => {
+                    info!("Now playing: '{}'", song);
+                    let _ = events.send(PlayerEvent::Ready { song, player_id });
                 }
-                SoundPlayerError::InvalidSpeed { speed } => {
-                    warn!("Invalid speed: {}", speed);
-                    return format!("Invalid speed: {}", speed);
+                Ok(false) => debug!("Dropping '{}', superseded while it was opening", song),
+                Err(e) => {
+                    log::log!(e.level(), "Failed to load '{}': {}", song, e.message());
+                    let _ = events.send(PlayerEvent::LoadFailed {
+                        song,
+                        player_id,
+                        error_kind: e.kind(),
+                        message: e.message(),
+                    });
                 }
-                SoundPlayerError::NoSongLoaded => {
-                    warn!("No song is currently loaded.");
-                    return format!("No song is currently loaded.");
+            }
+        });
+    }
+
+    /// Fades playback out once `delay` has passed. The timer is cancelled when the
+    /// returned sender is dropped, or when the player goes away first. Sending on
+    /// it fails once the timer has gone off.
+    fn spawn_sleep_timer(sound_player: Weak<Mutex<SoundPlayer<B>>>, delay: Duration) -> Sender<()> {
+        let (cancel, cancelled) = mpsc::channel();
+        thread::spawn(move || {
+            if cancelled.recv_timeout(delay) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+            drop(cancelled);
+            let Some(sound_player) = sound_player.upgrade() else {
+                return;
+            };
+            match lock(&sound_player).stop_with_fade(SLEEP_FADE) {
+                Ok(()) => info!("Sleep timer expired, stopping playback"),
+                Err(SoundPlayerError::NoSongLoaded) => info!("Sleep timer expired"),
+                Err(e) => error!("Sleep timer failed to stop playback: {}", e),
+            }
+        });
+        cancel
+    }
+
+    /// Sends the player's levels as an event every `interval` until the returned
+    /// sender is dropped or the player goes away. Quiet stretches send nothing.
+    fn spawn_level_meter(
+        sound_player: Weak<Mutex<SoundPlayer<B>>>,
+        player_id: Option<String>,
+        interval: Duration,
+        events: Sender<PlayerEvent>,
+    ) -> Sender<()> {
+        let (cancel, cancelled) = mpsc::channel();
+        thread::spawn(move || {
+            while cancelled.recv_timeout(interval) == Err(RecvTimeoutError::Timeout) {
+                let Some(sound_player) = sound_player.upgrade() else {
+                    return;
+                };
+                let levels = lock(&sound_player).take_levels();
+                if let Some(levels) = levels {
+                    let player_id = player_id.clone();
+                    let _ = events.send(PlayerEvent::Levels { player_id, levels });
                 }
-                SoundPlayerError::InvalidStreamHandle => {
-                    error!("Stream handle is no longer valid.");
-                    return format!("Stream handle is no longer valid.");
+            }
+        });
+        cancel
+    }
+
+    /// Events raised since the last call, oldest first.
+    pub fn pending_events(&self) -> Vec<PlayerEvent> {
+        self.events.try_iter().collect()
+    }
+
+    /// Runs `command` on the player called `player_id`, creating it if needed.
+    pub fn execute_command(
+        &mut self,
+        player_id: &str,
+        command: Command,
+    ) -> SoundPlayerResult<Option<Value>> {
+        let sound_player = self.player(player_id)?;
+        let mut player = lock(&sound_player);
+        // Reopened before the command runs rather than retrying the command once
+        // it failed, since by then it may have changed state (taken a queue
+        // entry, used up a resume point) that a second run would change again.
+        if !player.device_status().healthy {
+            warn!("Output stream lost; reopening it");
+            if let Err(e) = player.reopen_stream() {
+                error!("Failed to reopen the output stream: {}", e);
+            }
+        }
+        match command {
+            Command::Play {
+                song_name,
+                fade_in_ms,
+            } => {
+                let fade_in = fade_in_ms.map(Duration::from_millis);
+                if let Some(tracks) = player.play_matching(&song_name, fade_in)? {
+                    return Ok(Some(json!({ "tracks": tracks })));
                 }
-                SoundPlayerError::StreamError(source) => {
-                    return format!("Audio stream error: {}", source);
+                if let Some((source, generation)) = player.begin_background_load(&song_name)? {
+                    let player_id = (player_id != DEFAULT_PLAYER_ID).then(|| player_id.to_string());
+                    let _ = self.event_sender.send(PlayerEvent::Loading {
+                        song: song_name.clone(),
+                        player_id: player_id.clone(),
+                    });
+                    Self::spawn_background_load(
+                        Arc::downgrade(&sound_player),
+                        BackgroundLoad {
+                            song: song_name,
+                            source,
+                            generation,
+                            fade_in,
+                        },
+                        player_id,
+                        self.event_sender.clone(),
+                    );
+                    return Ok(Some(json!({ "loading": true })));
                 }
-                SoundPlayerError::FileOpenError { file, source } => {
-                    error!("Failed to open file '{}': {}", file, source);
-                    return format!("Failed to open file '{}': {}", file, source);
+                player.play(&song_name, fade_in)?
+            }
+            Command::Stop { fade_out_ms: None } => player.stop()?,
+            Command::Stop {
+                fade_out_ms: Some(ms),
+            } => player.stop_with_fade(Duration::from_millis(ms))?,
+            Command::PlayOverlay { song_name } => player.play_overlay(&song_name)?,
+            Command::PlayFrom {
+                song_name,
+                position,
+            } => player.play_from(&song_name, position)?,
+            Command::PlayReverse { song_name } => player.play_reverse(&song_name)?,
+            Command::PlayInline {
+                data_base64,
+                format,
+            } => player.play_inline(&data_base64, &format)?,
+            Command::StopAll => player.stop_overlays(),
+            Command::Pause => player.pause()?,
+            Command::Resume => {
+                if player.resume()? {
+                    return Ok(Some(json!({ "restarted": true })));
                 }
-                SoundPlayerError::DecodingError { file, source } => {
-                    error!("Failed to decode file '{}': {}", file, source);
-                    return format!("Failed to decode file '{}': {}", file, source);
+            }
+            Command::TogglePause => {
+                if player.toggle_pause()? {
+                    return Ok(Some(json!({ "restarted": true })));
                 }
             }
+            Command::Seek { position } => player.seek(position)?,
+            Command::SeekPercent { percent } => player.seek_percent(percent)?,
+            Command::SeekEnd { offset_secs } => {
+                let position = player.seek_end(offset_secs)?;
+                return Ok(Some(json!({ "position_secs": position })));
+            }
+            Command::SeekRelative { delta } => player.seek_relative(delta)?,
+            Command::Restart => player.restart()?,
+            Command::LoopAb { start, end } => player.set_ab_loop(start, end)?,
+            Command::LoopClear => player.clear_ab_loop(),
+            Command::Volume {
+                level,
+                clamp: false,
+            } => player.volume(level)?,
+            Command::Volume { level, clamp: true } => {
+                let volume = player.volume_clamped(level)?;
+                return Ok(Some(
+                    json!({ "volume": volume, "clamped": volume != level }),
+                ));
+            }
+            Command::VolumeDb { db } => {
+                let volume = player.set_volume_db(db)?;
+                return Ok(Some(json!({ "volume": volume })));
+            }
+            Command::VolumeRamp { level, ms } => {
+                player.ramp_volume(level, Duration::from_millis(ms))?
+            }
+            Command::VolumeFade { level, ms, curve } => {
+                player.fade_volume(level, Duration::from_millis(ms), curve)?
+            }
+            Command::MasterVolume { level } => player.set_master_volume(level)?,
+            Command::Speed {
+                factor,
+                clamp: false,
+            } => player.speed(factor)?,
+            Command::Speed {
+                factor,
+                clamp: true,
+            } => {
+                let speed = player.speed_clamped(factor)?;
+                return Ok(Some(json!({ "speed": speed, "clamped": speed != factor })));
+            }
+            Command::Tempo { factor } => player.tempo(factor)?,
+            Command::Balance { pan } => player.balance(pan)?,
+            Command::Equalizer { bands } => player.set_equalizer(bands)?,
+            Command::Mute => player.mute()?,
+            Command::Unmute => player.unmute()?,
+            Command::Normalize { enabled } => {
+                let gain_db = player.set_normalize(enabled);
+                return Ok(Some(json!({ "enabled": enabled, "gain_db": gain_db })));
+            }
+            Command::Mono { enabled } => player.set_mono(enabled),
+            Command::SetTrackGain { song_name, gain } => player.set_track_gain(&song_name, gain)?,
+            Command::GetPosition => {
+                let position = player.position()?;
+                return Ok(Some(json!({ "position_secs": position.as_secs_f64() })));
+            }
+            Command::TimeInfo => return Ok(serde_json::to_value(player.time_info()?).ok()),
+            Command::CanSeek => return Ok(Some(json!({ "seekable": player.can_seek()? }))),
+            Command::GetDuration => {
+                let duration = player.duration()?;
+                return Ok(Some(json!({
+                    "duration_secs": duration.map(|d| d.as_secs_f64())
+                })));
+            }
+            Command::Status => {
+                return Ok(serde_json::to_value(player.snapshot()).ok());
+            }
+            Command::Enqueue { song_name } => {
+                let queue_len = player.enqueue(&song_name);
+                return Ok(Some(json!({ "queue_len": queue_len })));
+            }
+            Command::Next => {
+                player.play_next()?;
+                return Ok(Some(json!({ "queue_len": player.queue_len() })));
+            }
+            Command::JumpTo { index, keep } => {
+                let song = player.jump_to(index, keep)?;
+                return Ok(Some(
+                    json!({ "song": song, "queue_len": player.queue_len() }),
+                ));
+            }
+            Command::Clear => player.clear_queue(),
+            Command::Crossfade { ms } => player.set_crossfade(Duration::from_millis(ms)),
+            Command::EndFade { ms } => player.set_end_fade(Duration::from_millis(ms)),
+            Command::Dequeue { index } => {
+                let queue_len = player.dequeue(index)?;
+                return Ok(Some(json!({ "queue_len": queue_len })));
+            }
+            Command::MoveInQueue { from, to } => {
+                player.move_in_queue(from, to)?;
+                return Ok(Some(json!({ "queue": player.queue_contents() })));
+            }
+            Command::History => {
+                return Ok(Some(json!({ "history": player.history() })));
+            }
+            Command::PlayPrevious => {
+                let song = player.play_previous()?;
+                return Ok(Some(json!({ "song": song })));
+            }
+            Command::GetQueue => {
+                return Ok(Some(json!({
+                    "current": player.snapshot().current_song,
+                    "queue": player.queue_contents(),
+                })));
+            }
+            Command::Repeat { mode } => player.set_repeat_mode(mode),
+            Command::RepeatCount { n } => player.set_repeat_count(n)?,
+            Command::Shuffle { seed } => player.shuffle_queue(seed),
+            Command::Metadata => {
+                return Ok(serde_json::to_value(player.metadata()?).ok());
+            }
+            Command::AudioInfo => {
+                return Ok(serde_json::to_value(player.audio_info()?).ok());
+            }
+            Command::Record { path } => {
+                let path = player.start_recording(&path)?;
+                return Ok(Some(json!({ "path": path })));
+            }
+            Command::StopRecording => {
+                let path = player.stop_recording();
+                return Ok(Some(json!({ "stopped": path.is_some(), "path": path })));
+            }
+            Command::ListDevices => {
+                let devices = player.list_devices()?;
+                return Ok(Some(json!({ "devices": devices })));
+            }
+            Command::SetDevice { name } => player.set_device(&name)?,
+            Command::CycleDevice => {
+                let device = player.cycle_device()?;
+                return Ok(Some(json!({ "device": device })));
+            }
+            Command::DeviceStatus => return Ok(serde_json::to_value(player.device_status()).ok()),
+            Command::SleepTimer { minutes } => {
+                let invalid = || SoundPlayerError::InvalidSleepTimer { minutes };
+                let delay = Duration::try_from_secs_f64(minutes * 60.0).map_err(|_| invalid())?;
+                let stops_at = SystemTime::now()
+                    .checked_add(delay)
+                    .ok_or_else(invalid)?
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                // Replacing the sender cancels any earlier timer.
+                self.sleep_timers.insert(
+                    player_id.to_string(),
+                    Self::spawn_sleep_timer(Arc::downgrade(&sound_player), delay),
+                );
+                return Ok(Some(json!({
+                    "stops_in_secs": delay.as_secs_f64(),
+                    "stops_at_unix": stops_at.as_secs_f64(),
+                })));
+            }
+            Command::SleepTimerCancel => {
+                // A timer that already went off leaves its sender behind.
+                let cancelled = self
+                    .sleep_timers
+                    .remove(player_id)
+                    .is_some_and(|cancel| cancel.send(()).is_ok());
+                return Ok(Some(json!({ "cancelled": cancelled })));
+            }
+            Command::LoadPlaylist { path } => {
+                let tracks = player.load_playlist(&path)?;
+                info!("Loaded {} tracks from playlist '{}'", tracks, path);
+                return Ok(Some(json!({ "tracks": tracks })));
+            }
+            Command::SubscribeLevels { interval_ms } => {
+                let interval = interval_ms.map_or(DEFAULT_LEVELS_INTERVAL, Duration::from_millis);
+                player.set_metering(true);
+                let event_player = (player_id != DEFAULT_PLAYER_ID).then(|| player_id.to_string());
+                // Replacing the sender stops any earlier meter thread.
+                self.level_meters.insert(
+                    player_id.to_string(),
+                    Self::spawn_level_meter(
+                        Arc::downgrade(&sound_player),
+                        event_player,
+                        interval,
+                        self.event_sender.clone(),
+                    ),
+                );
+                return Ok(Some(json!({ "interval_ms": interval.as_millis() as u64 })));
+            }
+            Command::UnsubscribeLevels => {
+                player.set_metering(false);
+                let unsubscribed = self.level_meters.remove(player_id).is_some();
+                return Ok(Some(json!({ "unsubscribed": unsubscribed })));
+            }
+            Command::Subscribe | Command::Unsubscribe => {
+                self.subscribed = matches!(command, Command::Subscribe);
+                return Ok(Some(json!({ "subscribed": self.subscribed })));
+            }
+            Command::Ping => {
+                return Ok(Some(json!({
+                    "reply": "pong",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "uptime_secs": self.started_at.elapsed().as_secs_f64(),
+                })));
+            }
+            Command::Metrics => return Ok(serde_json::to_value(&self.metrics).ok()),
+            Command::SetLogLevel { level } => {
+                // Logged before the change, so it shows when lowering the level too.
+                info!("Log level changed from {} to {}", log::max_level(), level);
+                log::set_max_level(level);
+                return Ok(Some(json!({ "level": level.as_str().to_lowercase() })));
+            }
+            Command::Help => return Ok(Some(json!({ "commands": COMMANDS }))),
+            Command::SaveState { path } => {
+                let path = state_path(&player, path)?;
+                player.save_state(&path)?;
+                info!("Saved session state to {}", path.display());
+            }
+            Command::RestoreState { path } => {
+                let path = state_path(&player, path)?;
+                player.load_state(&path)?;
+                info!("Restored session state from {}", path.display());
+            }
+        }
+        Ok(None)
+    }
+
+    /// Starts `target` on the default player as if it had been ordered: an M3U
+    /// playlist is queued with `loadplaylist`, anything else goes to `play`.
+    /// Failures, such as a missing file, are only logged.
+    pub fn autoplay(&mut self, target: &str) {
+        let command = if is_playlist(target) {
+            Command::LoadPlaylist {
+                path: target.to_string(),
+            }
         } else {
-            info!(
-                "Command '{}' with params '{}' executed successfully",
-                order.command_name,
-                order.parameters.join(" ")
-            );
-            return format!(
-                "Command '{}' with params '{}' executed successfully",
-                order.command_name,
-                order.parameters.join(" ")
+            Command::Play {
+                song_name: target.to_string(),
+                fade_in_ms: None,
+            }
+        };
+        match self.execute_command(DEFAULT_PLAYER_ID, command) {
+            Ok(_) => info!("Autoplaying '{}'", target),
+            Err(e) => warn!("Could not autoplay '{}': {}", target, e.message()),
+        }
+    }
+
+    /// Deserializes a raw text message into an `Order`, or a JSON array of them, and
+    /// processes it. Malformed JSON yields an error response instead of aborting.
+    pub fn process_message(&mut self, text: &str) -> Reply {
+        let reply = serde_json::from_str::<Value>(text).and_then(|value| {
+            if value.is_array() {
+                let orders = serde_json::from_value::<Vec<Order>>(value)?;
+                info!("Received batch of {} orders", orders.len());
+                Ok(Reply::Batch(self.process_orders(orders)))
+            } else {
+                let order = serde_json::from_value::<Order>(value)?;
+                info!(
+                    "Received order: {} {}",
+                    order.command_name,
+                    order.display_parameters()
+                );
+                Ok(Reply::Single(self.process_order(order)))
+            }
+        });
+        reply.unwrap_or_else(|e| {
+            let message = format!("Malformed order: {}", e);
+            warn!("{}", message);
+            self.metrics.record(None, Some("invalid_json"));
+            Reply::Single(CommandResponse::error("", "invalid_json", message))
+        })
+    }
+
+    /// Runs `orders` in sequence. A failing order doesn't stop the rest; every
+    /// order gets its own response, in the same position.
+    pub fn process_orders(&mut self, orders: Vec<Order>) -> Vec<CommandResponse> {
+        orders
+            .into_iter()
+            .map(|order| self.process_order(order))
+            .collect()
+    }
+
+    /// Executes `order` and builds its response, carrying over the order's `id`.
+    pub fn process_order(&mut self, order: Order) -> CommandResponse {
+        let id = order.id.clone();
+        let player_id = order.player_id.clone();
+        let command = find_command(&order.command_name).map(|spec| spec.name);
+        let response = self.respond(order).with_id(id).with_player_id(player_id);
+        self.metrics.record(command, response.error_kind);
+        response
+    }
+
+    fn respond(&mut self, order: Order) -> CommandResponse {
+        let cmd = match Command::try_from(&order) {
+            Ok(c) => c,
+            // A malformed order is the client's mistake, logged like the other
+            // client errors `SoundPlayerError::level` puts at warn.
+            Err(e) => {
+                let kind = e.kind();
+                let mut data = None;
+                let message = match e {
+                    CommandParseError::InvalidParameters => {
+                        let message = format!(
+                            "Invalid parameters in command: '{}'",
+                            order.display_parameters()
+                        );
+                        warn!("{}", message);
+                        message
+                    }
+                    CommandParseError::UnknownCommand => {
+                        let mut message = format!("Unknown command: '{}'", order.command_name);
+                        if let Some(suggestion) = suggest_command(&order.command_name) {
+                            message.push_str(&format!("; did you mean '{}'?", suggestion));
+                            data = Some(json!({ "suggestion": suggestion }));
+                        }
+                        warn!("{}", message);
+                        message
+                    }
+                };
+                return CommandResponse::error(&order.command_name, kind, message).with_data(data);
+            }
+        };
+
+        let player_id = order.player_id.as_deref().unwrap_or(DEFAULT_PLAYER_ID);
+        let notify = self.subscribed && cmd.changes_state();
+        match self.execute_command(player_id, cmd) {
+            Err(e) => {
+                let level = e.level();
+                let response = CommandResponse::from(e).with_command(&order.command_name);
+                log::log!(level, "{}", response.message);
+                response
+            }
+            Ok(data) => {
+                if notify && let Some(sound_player) = self.players.get(player_id) {
+                    let snapshot = lock(sound_player).snapshot();
+                    let _ = self.event_sender.send(PlayerEvent::StateChanged {
+                        player_id: (player_id != DEFAULT_PLAYER_ID).then(|| player_id.to_string()),
+                        snapshot,
+                    });
+                }
+                info!(
+                    "Command '{}' with params '{}' executed successfully",
+                    order.command_name,
+                    order.display_parameters()
+                );
+                CommandResponse::ok(
+                    &order.command_name,
+                    format!(
+                        "Command '{}' with params '{}' executed successfully",
+                        order.command_name,
+                        order.display_parameters()
+                    ),
+                )
+                .with_data(data)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_backend::{MockBackend, MockCall};
+    use crate::response::ResponseStatus;
+
+    fn mock_manager() -> (SoundPlayerManager<MockBackend>, MockBackend) {
+        let backend = MockBackend::new();
+        let options = PlayerOptions {
+            base_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("songs"),
+            ..PlayerOptions::default()
+        };
+        (
+            SoundPlayerManager::with_backend(backend.clone(), options),
+            backend,
+        )
+    }
+
+    fn order(command_name: &str, parameters: &[&str]) -> Order {
+        Order {
+            command_name: command_name.to_string(),
+            parameters: parameters.iter().map(|param| param.to_string()).collect(),
+            id: None,
+            player_id: None,
+        }
+    }
+
+    #[test]
+    fn transport_orders_reach_the_backend_in_order() {
+        let (mut manager, backend) = mock_manager();
+        for order in [
+            order("play", &["cone.mp3"]),
+            order("pause", &[]),
+            order("seek", &["1.5"]),
+            order("resume", &[]),
+            order("stop", &[]),
+        ] {
+            let response = manager.process_order(order);
+            assert_eq!(response.status, ResponseStatus::Ok, "{}", response.message);
+        }
+        let song = Path::new(env!("CARGO_MANIFEST_DIR")).join("songs/cone.mp3");
+        assert_eq!(
+            backend.calls(),
+            [
+                MockCall::Load(TrackSource::File(song)),
+                MockCall::Pause,
+                MockCall::Seek(Duration::from_secs_f64(1.5)),
+                MockCall::Play,
+                MockCall::Stop,
+            ]
+        );
+    }
+
+    #[test]
+    fn playfrom_seeks_before_the_track_is_heard() {
+        let (mut manager, backend) = mock_manager();
+        manager.process_order(order("playfrom", &["cone.mp3", "2"]));
+        let calls = backend.calls();
+        assert!(matches!(calls[0], MockCall::Load(_)), "{:?}", calls);
+        assert_eq!(
+            calls[1..],
+            [MockCall::Seek(Duration::from_secs(2)), MockCall::Play]
+        );
+    }
+
+    #[test]
+    fn lost_stream_is_reopened_without_skipping_a_track() {
+        let (mut manager, backend) = mock_manager();
+        for order in [
+            order("play", &["cone.mp3"]),
+            order("enqueue", &["one.mp3"]),
+            order("enqueue", &["two.mp3"]),
+        ] {
+            manager.process_order(order);
+        }
+        backend.lose_stream();
+
+        let response = manager.process_order(order("next", &[]));
+        assert_eq!(response.status, ResponseStatus::Ok, "{}", response.message);
+        let status = manager.process_order(order("status", &[])).data.unwrap();
+        assert_eq!(status["current_song"], "one.mp3");
+        let queue = manager.process_order(order("getqueue", &[])).data.unwrap();
+        assert_eq!(queue["queue"], json!(["two.mp3"]), "{}", queue);
+        // The current track is restored on the new stream, then the queue
+        // advances exactly once.
+        let calls = backend.calls();
+        let reopened = calls.iter().position(|call| *call == MockCall::Reopen);
+        let loads: Vec<&MockCall> = calls[reopened.unwrap()..]
+            .iter()
+            .filter(|call| matches!(call, MockCall::Load(_)))
+            .collect();
+        let songs = Path::new(env!("CARGO_MANIFEST_DIR")).join("songs");
+        assert_eq!(
+            loads,
+            [
+                &MockCall::Load(TrackSource::File(songs.join("cone.mp3"))),
+                &MockCall::Load(TrackSource::File(songs.join("one.mp3"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_a_pending_sleep_timer_can_be_cancelled() {
+        let (mut manager, _backend) = mock_manager();
+        manager.process_order(order("play", &["cone.mp3"]));
+        let cancel = || order("sleeptimercancel", &[]);
+
+        manager.process_order(order("sleeptimer", &["10"]));
+        let response = manager.process_order(cancel());
+        assert_eq!(response.data, Some(json!({ "cancelled": true })));
+
+        // 0.001 minutes, 60 ms.
+        manager.process_order(order("sleeptimer", &["0.001"]));
+        thread::sleep(Duration::from_millis(300));
+        let response = manager.process_order(cancel());
+        assert_eq!(response.data, Some(json!({ "cancelled": false })));
+    }
+
+    #[test]
+    fn rejected_orders_reach_no_backend() {
+        let (mut manager, backend) = mock_manager();
+        for order in [
+            order("seek", &["-1"]),
+            order("volume", &["loud"]),
+            order("bogus", &[]),
+            order("play", &["notes.txt"]),
+        ] {
+            let response = manager.process_order(order);
+            assert_eq!(
+                response.status,
+                ResponseStatus::Error,
+                "{}",
+                response.message
             );
         }
+        assert_eq!(backend.calls(), []);
     }
 }
@@ -0,0 +1,401 @@
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Range of tempo factors `TimeStretch` is tuned for; outside it the overlap-add
+/// artifacts become clearly audible.
+pub const MIN_TEMPO: f32 = 0.25;
+pub const MAX_TEMPO: f32 = 4.0;
+
+/// Length of each overlap-add segment. Longer windows smear transients, shorter
+/// ones make low notes warble.
+const WINDOW: Duration = Duration::from_millis(40);
+/// How far a segment may be shifted from its ideal position to line up with the
+/// previous one.
+const TOLERANCE: Duration = Duration::from_millis(10);
+/// Only every n-th frame takes part in the alignment search, to keep the cost down.
+const CORRELATION_STRIDE: usize = 4;
+
+/// State shared between a `TimeStretch` running on the audio thread and whoever
+/// controls it.
+#[derive(Debug)]
+pub struct StretchControl {
+    tempo: AtomicU32,
+    position_micros: AtomicU64,
+}
+
+impl StretchControl {
+    pub fn new(tempo: f32) -> Arc<Self> {
+        Arc::new(Self {
+            tempo: AtomicU32::new(tempo.to_bits()),
+            position_micros: AtomicU64::new(0),
+        })
+    }
+
+    pub fn tempo(&self) -> f32 {
+        f32::from_bits(self.tempo.load(Ordering::Relaxed))
+    }
+
+    pub fn set_tempo(&self, tempo: f32) {
+        self.tempo.store(tempo.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Position within the source of the audio currently being produced.
+    pub fn position(&self) -> Duration {
+        Duration::from_micros(self.position_micros.load(Ordering::Relaxed))
+    }
+
+    fn set_position(&self, position: Duration) {
+        self.position_micros
+            .store(position.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Changes the tempo of `input` without changing its pitch, using WSOLA
+/// (waveform-similarity overlap-add). At a tempo of exactly 1.0 samples are passed
+/// through untouched; otherwise every output block costs an alignment search of a
+/// few hundred thousand multiply-adds.
+pub struct TimeStretch<S> {
+    input: S,
+    control: Arc<StretchControl>,
+    channels: usize,
+    sample_rate: SampleRate,
+    /// Periodic Hann window; two copies offset by `hop` sum to one.
+    window: Vec<f32>,
+    hop: usize,
+    tolerance: usize,
+    /// Interleaved input that hasn't been consumed yet.
+    buffer: Vec<Sample>,
+    /// Absolute frame index of the first frame in `buffer`.
+    buffer_start: u64,
+    input_done: bool,
+    stretch: Option<StretchState>,
+    output: Vec<Sample>,
+    output_pos: usize,
+}
+
+struct StretchState {
+    /// Ideal start of the next segment, in frames from the start of the buffer.
+    next_pos: f64,
+    /// Where the previous segment would have continued; `None` before the first one.
+    natural: Option<usize>,
+    /// Windowed second half of the previous segment, waiting to be overlapped.
+    tail: Vec<Sample>,
+}
+
+impl<S: Source> TimeStretch<S> {
+    pub fn new(input: S, control: Arc<StretchControl>) -> Self {
+        let channels = input.channels().max(1) as usize;
+        let sample_rate = input.sample_rate();
+        let frames = |duration: Duration| (sample_rate as f32 * duration.as_secs_f32()) as usize;
+        let hop = (frames(WINDOW) / 2).max(1);
+        let window = (0..hop * 2)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / (hop * 2) as f32).cos())
+            .collect();
+        Self {
+            input,
+            control,
+            channels,
+            sample_rate,
+            window,
+            hop,
+            tolerance: frames(TOLERANCE),
+            buffer: Vec::new(),
+            buffer_start: 0,
+            input_done: false,
+            stretch: None,
+            output: Vec::new(),
+            output_pos: 0,
+        }
+    }
+
+    fn buffered_frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// Reads from the input until `frames` frames are buffered or it runs out.
+    fn fill(&mut self, frames: usize) {
+        while !self.input_done && self.buffered_frames() < frames {
+            match self.input.next() {
+                Some(sample) => self.buffer.push(sample),
+                None => self.input_done = true,
+            }
+        }
+    }
+
+    fn drain(&mut self, frames: usize) {
+        let frames = frames.min(self.buffered_frames());
+        self.buffer.drain(..frames * self.channels);
+        self.buffer_start += frames as u64;
+    }
+
+    fn publish_position(&self, frame: u64) {
+        self.control.set_position(Duration::from_secs_f64(
+            frame as f64 / self.sample_rate as f64,
+        ));
+    }
+
+    /// Sum of all channels of one frame.
+    fn mono(&self, frame: usize) -> f32 {
+        let start = frame * self.channels;
+        self.buffer[start..start + self.channels].iter().sum()
+    }
+
+    /// Offset in `lo..=hi` whose next `hop` frames best match those at `natural`.
+    fn best_alignment(&self, natural: usize, lo: usize, hi: usize) -> usize {
+        let reference: Vec<f32> = (0..self.hop)
+            .step_by(CORRELATION_STRIDE)
+            .map(|f| self.mono(natural + f))
+            .collect();
+        let mut best = (lo, f32::MIN);
+        for candidate in lo..=hi {
+            let score: f32 = (0..self.hop)
+                .step_by(CORRELATION_STRIDE)
+                .zip(&reference)
+                .map(|(f, r)| self.mono(candidate + f) * r)
+                .sum();
+            if score > best.1 {
+                best = (candidate, score);
+            }
+        }
+        best.0
+    }
+
+    /// Produces the next block of output. Leaves `output` empty once the input is
+    /// exhausted.
+    fn refill(&mut self) {
+        self.output.clear();
+        self.output_pos = 0;
+        let tempo = self.control.tempo();
+
+        let Some(mut state) = self.stretch.take() else {
+            if tempo == 1.0 {
+                self.fill(self.hop);
+                self.publish_position(self.buffer_start);
+                let frames = self.hop.min(self.buffered_frames());
+                self.output
+                    .extend_from_slice(&self.buffer[..frames * self.channels]);
+                self.drain(frames);
+            } else {
+                self.stretch = Some(StretchState {
+                    next_pos: 0.0,
+                    natural: None,
+                    tail: Vec::new(),
+                });
+                self.refill();
+            }
+            return;
+        };
+
+        let window_len = self.window.len();
+        if tempo == 1.0 {
+            // Cross-fade from the last segment back into the plain input.
+            self.output.append(&mut state.tail);
+            if let Some(natural) = state.natural {
+                self.fill(natural + self.hop);
+                let frames = self.hop.min(self.buffered_frames().saturating_sub(natural));
+                for f in 0..frames {
+                    for c in 0..self.channels {
+                        let sample = self.buffer[(natural + f) * self.channels + c];
+                        let i = f * self.channels + c;
+                        if i < self.output.len() {
+                            self.output[i] += sample * self.window[f];
+                        } else {
+                            self.output.push(sample * self.window[f]);
+                        }
+                    }
+                }
+                self.drain(natural + frames);
+            }
+            return;
+        }
+
+        let ideal = state.next_pos.round() as usize;
+        self.fill(ideal + self.tolerance + window_len);
+        let available = self.buffered_frames();
+        if available < ideal + window_len {
+            // Not enough input left for a full segment: let the last one ring out.
+            self.output.append(&mut state.tail);
+            self.drain(available);
+            self.stretch = Some(state);
+            return;
+        }
+
+        let lo = ideal.saturating_sub(self.tolerance);
+        let hi = (ideal + self.tolerance).min(available - window_len);
+        let start = match state.natural {
+            Some(natural) => self.best_alignment(natural, lo, hi),
+            None => ideal,
+        };
+
+        let mut tail = Vec::with_capacity(self.hop * self.channels);
+        for f in 0..window_len {
+            for c in 0..self.channels {
+                let sample = self.buffer[(start + f) * self.channels + c];
+                if f < self.hop {
+                    // The very first segment has nothing to overlap with, so its
+                    // leading half is not faded in.
+                    let faded = match state.natural {
+                        Some(_) => sample * self.window[f],
+                        None => sample,
+                    };
+                    let overlap = state.tail.get(f * self.channels + c).copied();
+                    self.output.push(faded + overlap.unwrap_or_default());
+                } else {
+                    tail.push(sample * self.window[f]);
+                }
+            }
+        }
+        self.publish_position(self.buffer_start + start as u64);
+
+        state.tail = tail;
+        state.next_pos += self.hop as f64 * tempo as f64;
+        let natural = start + self.hop;
+        let consumed = natural.min((state.next_pos as usize).saturating_sub(self.tolerance));
+        self.drain(consumed);
+        state.natural = Some(natural - consumed);
+        state.next_pos -= consumed as f64;
+        self.stretch = Some(state);
+    }
+}
+
+impl<S: Source> Iterator for TimeStretch<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.output_pos >= self.output.len() {
+            self.refill();
+        }
+        let sample = self.output.get(self.output_pos).copied();
+        self.output_pos += 1;
+        sample
+    }
+}
+
+impl<S: Source> Source for TimeStretch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels as ChannelCount
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+        self.buffer.clear();
+        self.buffer_start = (position.as_secs_f64() * self.sample_rate as f64) as u64;
+        self.input_done = false;
+        self.stretch = None;
+        self.output.clear();
+        self.output_pos = 0;
+        self.publish_position(self.buffer_start);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const RATE: SampleRate = 44_100;
+
+    /// One second of a 440 Hz stereo sine, with the right channel at half level.
+    fn sine() -> Vec<Sample> {
+        (0..RATE as usize)
+            .flat_map(|n| {
+                let s = (2.0 * PI * 440.0 * n as f32 / RATE as f32).sin();
+                [s, s * 0.5]
+            })
+            .collect()
+    }
+
+    fn stretch(tempo: f32) -> (TimeStretch<SamplesBuffer>, Arc<StretchControl>) {
+        let control = StretchControl::new(tempo);
+        let source = SamplesBuffer::new(2, RATE, sine());
+        (TimeStretch::new(source, control.clone()), control)
+    }
+
+    /// Runs `stretch` to the end, returning its output and every position it
+    /// reported along the way.
+    fn run(
+        stretch: TimeStretch<SamplesBuffer>,
+        control: &StretchControl,
+    ) -> (Vec<Sample>, Vec<Duration>) {
+        let mut output = Vec::new();
+        let mut positions = Vec::new();
+        for sample in stretch {
+            output.push(sample);
+            positions.push(control.position());
+        }
+        (output, positions)
+    }
+
+    fn assert_near(actual: usize, expected: usize, slack: usize) {
+        assert!(
+            actual.abs_diff(expected) <= slack,
+            "{actual} is not within {slack} of {expected}"
+        );
+    }
+
+    #[test]
+    fn normal_tempo_passes_samples_through() {
+        let (stretch, control) = stretch(1.0);
+        let (output, positions) = run(stretch, &control);
+        assert_eq!(output, sine());
+        assert!(positions.is_sorted());
+        assert!(*positions.last().unwrap() > Duration::from_millis(950));
+    }
+
+    #[test]
+    fn faster_tempo_shortens_the_output() {
+        let (stretch, control) = stretch(2.0);
+        let hop = stretch.hop;
+        let (output, positions) = run(stretch, &control);
+        assert_near(output.len() / 2, RATE as usize / 2, hop * 2);
+        assert!(positions.is_sorted());
+        assert!(*positions.last().unwrap() > Duration::from_millis(900));
+    }
+
+    #[test]
+    fn slower_tempo_lengthens_the_output() {
+        let (stretch, control) = stretch(0.5);
+        let hop = stretch.hop;
+        let (output, positions) = run(stretch, &control);
+        assert_near(output.len() / 2, RATE as usize * 2, hop * 2);
+        assert!(positions.is_sorted());
+        assert!(*positions.last().unwrap() > Duration::from_millis(900));
+    }
+
+    #[test]
+    fn stretching_keeps_channels_apart_and_in_range() {
+        let (stretch, control) = stretch(1.5);
+        let (output, _) = run(stretch, &control);
+        for frame in output.chunks_exact(2) {
+            assert!(frame[0].abs() <= 1.01, "{frame:?}");
+            assert!((frame[1] - frame[0] * 0.5).abs() < 1e-4, "{frame:?}");
+        }
+    }
+
+    #[test]
+    fn seeking_reports_the_new_position() {
+        let (mut stretch, control) = stretch(2.0);
+        stretch.by_ref().take(10_000).count();
+        stretch.try_seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(control.position(), Duration::from_millis(500));
+        stretch.next();
+        assert!(control.position() >= Duration::from_millis(500));
+    }
+}
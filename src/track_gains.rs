@@ -0,0 +1,70 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Largest boost or cut, in dB, a track can be given by hand.
+pub const MAX_TRACK_GAIN_DB: f32 = 24.0;
+
+/// Gain offsets in dB set by hand for particular tracks, keyed by the resolved
+/// file path (or the URL). Kept in a JSON file, if one is configured, which is
+/// rewritten on every change; otherwise they last until the player exits.
+#[derive(Debug, Default)]
+pub struct TrackGains {
+    file: Option<PathBuf>,
+    gains: BTreeMap<String, f32>,
+}
+
+impl TrackGains {
+    /// Reads the gains saved in `file`. A file that doesn't exist yet starts an
+    /// empty map; one that can't be read or parsed is warned about and ignored,
+    /// and gets replaced on the next change.
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let gains = file
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| match read(path) {
+                Ok(gains) => Some(gains),
+                Err(e) => {
+                    warn!("Ignoring track gains in {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self { file, gains }
+    }
+
+    /// Offset of the track at `key` in dB; 0 for tracks without one.
+    pub fn get(&self, key: &str) -> f32 {
+        self.gains.get(key).copied().unwrap_or(0.0)
+    }
+
+    /// Sets the offset of the track at `key`, 0 removing it, and saves the map.
+    /// The file is replaced atomically, like the session state.
+    pub fn set(&mut self, key: String, db: f32) -> io::Result<()> {
+        if db == 0.0 {
+            self.gains.remove(&key);
+        } else {
+            self.gains.insert(key, db);
+        }
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(&self.gains)?;
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, json)?;
+        fs::rename(&temp, path)
+    }
+
+    /// The file the gains are saved to, if any.
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+}
+
+fn read(path: &Path) -> io::Result<BTreeMap<String, f32>> {
+    let json = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}